@@ -0,0 +1,17 @@
+use std::process::Command;
+
+/// Records the commit the binary is built from, which is written to the evaluation output.
+fn main() {
+    let commit = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=GIT_COMMIT={}", commit);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    // Refs are moved into this file by git gc
+    println!("cargo:rerun-if-changed=.git/packed-refs");
+}
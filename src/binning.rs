@@ -0,0 +1,227 @@
+use crate::cli::Config;
+use crate::parse::Dataset;
+use crate::split::{self, SplitStrategy};
+use crate::trace::Column;
+
+use indexmap::IndexSet;
+use ordered_float::OrderedFloat;
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::{convert::TryFrom, error::Error, str::FromStr};
+
+/// Strategy to group the values of a numeric attribute into bins.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "strategy", rename_all = "snake_case")]
+pub enum Binning {
+    /// Bins of equal width, e.g. speed in steps of 10 km/h
+    FixedWidth { width: f64 },
+    /// Bins holding the same share of the values of the observed traces of all users
+    Quantile { num_bins: usize },
+    /// Sectors of equal angle for directions in degrees, where the first sector is centered around north
+    Circular { num_sectors: usize },
+}
+
+impl Binning {
+    /// Parses a binning from a specification of the form `strategy:parameter`, e.g. `fixed_width:10`.
+    pub fn from_spec(spec: &str) -> Result<Binning, Box<dyn Error>> {
+        let (strategy, param) = match spec.split_once(':') {
+            Some(parts) => parts,
+            None => return Err(format!("Error: Invalid binning {:?}.", spec).into()),
+        };
+        let binning = match strategy {
+            "fixed_width" => Binning::FixedWidth {
+                width: param.parse::<f64>()?,
+            },
+            "quantile" => Binning::Quantile {
+                num_bins: param.parse::<usize>()?,
+            },
+            "circular" => Binning::Circular {
+                num_sectors: param.parse::<usize>()?,
+            },
+            x => return Err(format!("Error: The supplied binning strategy does not exist: {:?}", x).into()),
+        };
+        binning.validate()?;
+        Ok(binning)
+    }
+
+    /// Checks that the parameter of the binning is valid.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        let is_valid = match self {
+            Binning::FixedWidth { width } => *width > 0.,
+            Binning::Quantile { num_bins } => *num_bins > 0,
+            Binning::Circular { num_sectors } => *num_sectors > 0,
+        };
+        if !is_valid {
+            return Err(format!("Error: Invalid parameter for binning {:?}.", self).into());
+        }
+        Ok(())
+    }
+
+    /// Returns the label of the bin the value falls into. The edges are only used for quantile bins.
+    fn label(&self, value: f64, edges: &[f64]) -> String {
+        match self {
+            Binning::FixedWidth { width } => {
+                let lower = (value / width).floor() * width;
+                format!("[{}, {})", lower, lower + width)
+            }
+            Binning::Quantile { .. } => {
+                format!("q{}", edges.iter().filter(|edge| **edge < value).count())
+            }
+            Binning::Circular { num_sectors } => {
+                let sector_size = 360. / *num_sectors as f64;
+                let sector =
+                    ((value.rem_euclid(360.) + sector_size / 2.) / sector_size).floor() as usize % num_sectors;
+                format!("{}°", sector as f64 * sector_size)
+            }
+        }
+    }
+}
+
+/// Computes the edges between quantile bins from the values with the given weights.
+fn quantile_edges(mut values: Vec<(f64, u32)>, num_bins: usize) -> Vec<f64> {
+    values.sort_unstable_by_key(|(value, _)| OrderedFloat(*value));
+    let total: u64 = values.iter().map(|(_, weight)| *weight as u64).sum();
+    let mut edges: Vec<f64> = Vec::with_capacity(num_bins);
+    let mut cumulative: u64 = 0;
+    let mut bin = 1;
+    for (value, weight) in values.into_iter() {
+        cumulative += weight as u64;
+        while bin < num_bins && cumulative * num_bins as u64 >= bin as u64 * total {
+            // Skip duplicate edges so that no bin is empty
+            if edges.last() != Some(&value) {
+                edges.push(value);
+            }
+            bin += 1;
+        }
+    }
+    edges
+}
+
+/// Replaces the values of the selected numeric attributes with binning by the bins they fall into.
+///
+/// The dictionaries of the binned attributes are replaced by the labels of the bins. Empty values are kept as they
+/// are. Quantiles are computed on the observed traces of the configured split, so that the binning does not leak
+/// information about the target traces. The random split is only drawn when sampling, so it cannot be combined with
+/// quantile bins.
+pub fn apply<C: Column>(config: &Config, dataset: &mut Dataset<C>) -> Result<(), Box<dyn Error>> {
+    let has_quantiles = config.fields.iter().any(|field| {
+        matches!(config.schema.attributes[*field].binning, Some(Binning::Quantile { .. }))
+    });
+    let user_to_split_map = if has_quantiles {
+        if SplitStrategy::from_str(&config.split).unwrap() == SplitStrategy::Random {
+            return Err("Error: Quantile binning requires a deterministic split, not the random split.".into());
+        }
+        // The rng is only used by the random split
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        split::split_histories(config, &dataset.user_to_traces_map, &mut rng)?
+    } else {
+        Default::default()
+    };
+
+    for field in config.fields.iter() {
+        let binning = match &config.schema.attributes[*field].binning {
+            Some(binning) => binning,
+            None => continue,
+        };
+
+        let dictionary = &dataset.dictionaries[*field];
+        let values: Vec<Option<f64>> = dictionary
+            .iter()
+            .map(|raw| raw.trim().parse::<f64>().ok())
+            .collect();
+
+        let edges = match binning {
+            Binning::Quantile { num_bins } => {
+                let mut weights: Vec<u32> = vec![0; dictionary.len()];
+                for (user, traces) in dataset.user_to_traces_map.iter() {
+                    for idx in user_to_split_map[user].observed.iter() {
+                        traces[*idx].columns[*field].for_each_value(|value, count| {
+                            weights[value as usize] += count;
+                        });
+                    }
+                }
+                let observed = values
+                    .iter()
+                    .zip(weights)
+                    .filter_map(|(value, weight)| match value {
+                        Some(value) if weight > 0 => Some((*value, weight)),
+                        _ => None,
+                    })
+                    .collect();
+                quantile_edges(observed, *num_bins)
+            }
+            _ => Vec::new(),
+        };
+
+        // Keep the empty value at the position of missing values
+        let mut bins: IndexSet<String> = std::iter::once(String::new()).collect();
+        let mapping: Vec<u32> = values
+            .iter()
+            .map(|value| {
+                let label = match value {
+                    Some(value) => binning.label(*value, &edges),
+                    None => String::new(),
+                };
+                u32::try_from(bins.insert_full(label).0).unwrap()
+            })
+            .collect();
+
+        for traces in dataset.user_to_traces_map.values_mut() {
+            for trace in traces.iter_mut() {
+                trace.columns[*field].map_values(|value| mapping[value as usize]);
+            }
+        }
+        dataset.dictionaries[*field] = bins;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(Binning::from_spec("fixed_width:10").unwrap(), Binning::FixedWidth { width: 10. });
+        assert_eq!(Binning::from_spec("quantile:4").unwrap(), Binning::Quantile { num_bins: 4 });
+        assert_eq!(Binning::from_spec("circular:8").unwrap(), Binning::Circular { num_sectors: 8 });
+        for spec in ["fixed_width", "fixed_width:0", "fixed_width:-5", "quantile:0", "circular:x", "log:2"].iter() {
+            assert!(Binning::from_spec(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn labels_fixed_width_bins() {
+        let binning = Binning::FixedWidth { width: 10. };
+        assert_eq!(binning.label(0., &[]), "[0, 10)");
+        assert_eq!(binning.label(9.99, &[]), "[0, 10)");
+        assert_eq!(binning.label(10., &[]), "[10, 20)");
+        assert_eq!(binning.label(-0.5, &[]), "[-10, 0)");
+    }
+
+    #[test]
+    fn labels_circular_sectors_around_north() {
+        let binning = Binning::Circular { num_sectors: 4 };
+        assert_eq!(binning.label(0., &[]), "0°");
+        assert_eq!(binning.label(44., &[]), "0°");
+        assert_eq!(binning.label(46., &[]), "90°");
+        assert_eq!(binning.label(350., &[]), "0°");
+        assert_eq!(binning.label(-90., &[]), "270°");
+        assert_eq!(binning.label(540., &[]), "180°");
+    }
+
+    #[test]
+    fn computes_weighted_quantiles() {
+        let values: Vec<(f64, u32)> = (1..=8).rev().map(|value| (value as f64, 1)).collect();
+        let edges = quantile_edges(values, 4);
+        assert_eq!(edges, [2., 4., 6.]);
+        let binning = Binning::Quantile { num_bins: 4 };
+        let labels: Vec<String> = (1..=8).map(|value| binning.label(value as f64, &edges)).collect();
+        assert_eq!(labels, ["q0", "q0", "q1", "q1", "q2", "q2", "q3", "q3"]);
+
+        // A value that holds most of the weight spans several quantiles, which yield a single edge
+        let edges = quantile_edges(vec![(1., 1), (2., 10), (3., 1)], 4);
+        assert_eq!(edges, [2.]);
+        assert!(quantile_edges(Vec::new(), 4).is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+use crate::binning::Binning;
+use crate::cli::Config;
+use crate::parse::{needs_distance, Dataset};
+
+use memmap2::Mmap;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Magic bytes at the start of every cache file.
+const MAGIC: &[u8; 4] = b"LBMB";
+
+/// Version of the cache format. Must be increased whenever the layout of the cached data changes.
+const CACHE_VERSION: u32 = 3;
+
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+/// Computes the key of the cache, which is a hash of the identity of the input file and all parameters that affect
+/// the parsing.
+///
+/// The input file is identified by its canonical path, size and modification time, which does not require reading
+/// it. With `--cache_content_hash`, it is identified by a hash of its content instead, which survives copying or
+/// touching the file.
+pub fn cache_key(config: &Config) -> Result<String, Box<dyn Error>> {
+    let mut hasher = Sha256::new();
+    hasher.update(CACHE_VERSION.to_le_bytes());
+    if config.cache_content_hash {
+        io::copy(&mut BufReader::new(File::open(&config.path)?), &mut hasher)?;
+    } else {
+        let metadata = fs::metadata(&config.path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        let identity = serde_json::json!([
+            fs::canonicalize(&config.path)?.to_string_lossy(),
+            metadata.len(),
+            modified.as_secs(),
+            modified.subsec_nanos(),
+        ]);
+        hasher.update(identity.to_string().as_bytes());
+    }
+
+    // Quantile bins are computed on the observed traces, which depend on the split
+    let has_quantiles = config.fields.iter().any(|field| {
+        matches!(config.schema.attributes[*field].binning, Some(Binning::Quantile { .. }))
+    });
+    let split = if has_quantiles {
+        serde_json::json!([config.split, config.split_ratio, config.split_cutoff])
+    } else {
+        serde_json::Value::Null
+    };
+    let params = serde_json::json!({
+        "approach": config.approach,
+        "schema": config.schema,
+        "fields": config.fields,
+        "segmentation": config.segmentation,
+        "delay_limit": config.delay_limit,
+        "stay_radius": config.stay_radius,
+        "stay_duration": config.stay_duration,
+        "stop_duration": config.stop_duration,
+        "speed_field": config.speed_field,
+        "window": config.window,
+        "max_trace_len": config.max_trace_len,
+        "min_trace_len": config.min_trace_len,
+        "max_trace_duration": config.max_trace_duration,
+        "min_num_traces": config.min_num_traces,
+        "sort": config.sort,
+        "osm": config.osm,
+        "max_snap_distance": config.max_snap_distance,
+        "distance": needs_distance(config),
+        "split": split,
+    });
+    hasher.update(params.to_string().as_bytes());
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Returns the path of the cache file for the given key.
+pub fn cache_path(config: &Config, key: &str) -> PathBuf {
+    Path::new(&config.cache_dir).join(format!("{}-{}.bin", config.approach, key))
+}
+
+/// Writes the dataset to a versioned cache file.
+pub fn write<C: Serialize>(path: &Path, dataset: &Dataset<C>) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&CACHE_VERSION.to_le_bytes())?;
+    bincode::serialize_into(&mut writer, dataset)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Loads the dataset from the cache file by memory-mapping it.
+///
+/// Returns None if there is no cache file or if it was written with a different version of the cache format.
+pub fn read<C: DeserializeOwned>(path: &Path) -> Result<Option<Dataset<C>>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(path)?;
+    // Safety: The cache file is not expected to be modified while it is being read
+    let mmap = unsafe { Mmap::map(&file)? };
+    if mmap.len() < HEADER_LEN || &mmap[..MAGIC.len()] != MAGIC {
+        log::warn!("Ignoring invalid cache file {:?}.", path);
+        return Ok(None);
+    }
+    let mut version = [0u8; 4];
+    version.copy_from_slice(&mmap[MAGIC.len()..HEADER_LEN]);
+    if u32::from_le_bytes(version) != CACHE_VERSION {
+        log::warn!("Ignoring cache file {:?} of an outdated version.", path);
+        return Ok(None);
+    }
+    Ok(Some(bincode::deserialize(&mmap[HEADER_LEN..])?))
+}
+
+/// Loads the dataset from the cache if it has been prepared before, otherwise parses the raw data. The key is only
+/// computed if the cache directory exists.
+pub fn load_or_parse<C, F>(config: &Config, parse: F) -> Result<Dataset<C>, Box<dyn Error>>
+where
+    C: DeserializeOwned,
+    F: Fn(&Config) -> Result<Dataset<C>, Box<dyn Error>>,
+{
+    if !Path::new(&config.cache_dir).is_dir() {
+        return parse(config);
+    }
+    let path = cache_path(config, &cache_key(config)?);
+    match read(&path)? {
+        Some(dataset) => {
+            log::info!("Loaded cached dataset from {:?}.", path);
+            Ok(dataset)
+        }
+        None => parse(config),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli;
+
+    fn config(path: &Path, args: &[&str]) -> Config {
+        let path = path.to_str().unwrap();
+        cli::config_from_args(["linkage", "--path", path].iter().chain(args.iter())).unwrap()
+    }
+
+    #[test]
+    fn key_changes_with_parsing_parameters() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        fs::write(&path, "user_id,timestamp\n").unwrap();
+        let key = cache_key(&config(&path, &[])).unwrap();
+        assert_eq!(cache_key(&config(&path, &[])).unwrap(), key);
+
+        // Parameters of the sampling and the evaluation do not affect the parsed dataset
+        assert_eq!(cache_key(&config(&path, &["--seed", "7", "--user_sample_size", "3"])).unwrap(), key);
+
+        let changed = [
+            vec!["--approach", "sequence"],
+            vec!["--delay_limit", "60"],
+            vec!["--min_num_traces", "5"],
+            vec!["--fields", "street"],
+            vec!["--missing", "street:skip"],
+            vec!["--segmentation", "stay_point"],
+        ];
+        for args in changed.iter() {
+            assert_ne!(cache_key(&config(&path, args)).unwrap(), key, "{:?}", args);
+        }
+
+        // The split only matters for quantile bins
+        let quantiles = ["--binning", "speed:quantile:4", "--fields", "speed"];
+        let key = cache_key(&config(&path, &quantiles)).unwrap();
+        let split = [&quantiles[..], &["--split_ratio", "0.8"]].concat();
+        assert_ne!(cache_key(&config(&path, &split)).unwrap(), key);
+        assert_eq!(
+            cache_key(&config(&path, &["--split_ratio", "0.8", "--fields", "speed"])).unwrap(),
+            cache_key(&config(&path, &["--fields", "speed"])).unwrap()
+        );
+    }
+
+    #[test]
+    fn key_changes_with_input_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv");
+        fs::write(&path, "user_id,timestamp\n").unwrap();
+        let config = config(&path, &["--cache_content_hash", "true"]);
+        let key = cache_key(&config).unwrap();
+        fs::write(&path, "user_id,timestamp\n").unwrap();
+        assert_eq!(cache_key(&config).unwrap(), key);
+        fs::write(&path, "user_id,timestamp\nuser_1,0\n").unwrap();
+        assert_ne!(cache_key(&config).unwrap(), key);
+    }
+}
@@ -1,11 +1,15 @@
-use crate::{parse::DataFields};
+use crate::binning::Binning;
+use crate::schema::{self, MissingPolicy, Schema};
+use crate::utils;
 
-use std::str::FromStr;
+use chrono::Utc;
+use serde::Serialize;
+use std::{ffi::OsString, str::FromStr};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Config {
     pub delay_limit: f64,
-    pub fields: Vec<DataFields>,
+    pub fields: Vec<usize>,
     pub max_trace_len: usize,
     pub min_trace_len: usize,
     pub max_trace_duration: f64,
@@ -23,16 +27,116 @@ pub struct Config {
     pub approach: String,
     pub scope: String,
     pub reverse: bool,
+    pub schema: Schema,
+    pub osm: Option<String>,
+    pub max_snap_distance: f64,
+    pub sort: bool,
+    pub sort_buffer_size: usize,
+    pub user_id_map: Option<String>,
+    pub command: String,
+    pub cache_dir: String,
+    pub cache_content_hash: bool,
+    pub segmentation: String,
+    pub stay_radius: f64,
+    pub stay_duration: f64,
+    pub stop_duration: f64,
+    pub speed_field: String,
+    pub window: f64,
+    pub report_path: String,
+    pub generator: Option<GeneratorConfig>,
+    pub path_to_map: Option<String>,
+    pub save_map: Option<String>,
+    pub split: String,
+    pub split_ratio: f64,
+    pub split_cutoff: Option<String>,
+    pub split_gap_days: f64,
+    pub repeat: Option<RepeatConfig>,
+    pub open_world_fraction: f64,
+    pub rejection_threshold: Option<f64>,
+    pub det_path: Option<String>,
+    pub stratify: String,
+    pub num_strata: usize,
+    pub ranking_path: Option<String>,
+    pub ranking_top_n: usize,
+    pub top_k: Vec<usize>,
+    pub bootstrap_samples: usize,
+    pub comparison: Option<ComparisonConfig>,
+    pub output: String,
+    pub store: Option<String>,
+    pub results: Option<ResultsConfig>,
+    /// Missing-value policy applied to all selected fields by the missing_report command, which tags its runs with it
+    pub missing_policy: Option<MissingPolicy>,
+    /// Random UUID identifying the run in the evaluation output
+    #[serde(skip)]
+    pub run_id: String,
+    /// Start time of the run in RFC 3339 format
+    #[serde(skip)]
+    pub timestamp: String,
+}
+
+/// Parameters of the repeated evaluation of linkage attacks.
+#[derive(Debug, Clone, Serialize)]
+pub struct RepeatConfig {
+    pub num_partitions: usize,
+    pub num_repeats: usize,
+    pub parallel_partitions: usize,
+    pub experiment_path: String,
+}
+
+/// Paths of the rankings of two configurations compared by paired significance tests.
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonConfig {
+    pub baseline_path: String,
+    pub candidate_path: String,
+    pub comparison_path: String,
+}
+
+/// Queries of the runs in the experiment store.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultsConfig {
+    /// Configuration keys with the values the listed runs must have
+    pub filters: Vec<(String, String)>,
+    pub columns: Vec<String>,
+    pub group_by: Option<String>,
+    /// Ids (or unique prefixes) of two runs to compare
+    pub diff: Option<(String, String)>,
+}
+
+/// Parameters of the population model of the synthetic dataset generator.
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratorConfig {
+    /// Path of the synthetic dataset
+    pub output: String,
+    /// Overwrite an existing file at the output path
+    pub force: bool,
+    pub num_users: usize,
+    pub num_days: usize,
+    pub num_streets: usize,
+    pub num_shared_routes: usize,
+    pub route_sharing: f64,
+    pub noise: f64,
+    pub missing_rate: f64,
+    pub sample_interval: f64,
 }
 
 
 pub fn get_cli_config() -> Result<Config, clap::Error> {
+    config_from_args(std::env::args_os())
+}
+
+/// Parses the configuration from the given command line arguments, the first of which is the binary name.
+pub fn config_from_args<I, T>(args: I) -> Result<Config, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
     let matches = clap::App::new("Linking-by-Mobility-Behavior")
         .version("1.0")
         .author("Felix John")
         .arg(
             clap::Arg::new("approach")
                 .long("approach")
+                .global(true)
                 .about("Sequence alignment-based or frequency-based approach.")
                 .possible_values(&["sequence", "frequency"])
                 .default_value("frequency"),
@@ -40,6 +144,7 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
         .arg(
             clap::Arg::new("scoring_matrix")
                 .long("scoring_matrix")
+                .global(true)
                 .allow_hyphen_values(true)
                 .about("The scoring matrix to use for the alignment approach: ['equal', 'align', 'insert', 'delete'].")
                 .multiple_values(true)
@@ -48,6 +153,7 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
         .arg(
             clap::Arg::new("scope")
                 .long("scope")
+                .global(true)
                 .about("The scope of the alignment algorithm: local or global.")
                 .possible_values(&["local", "global"])
                 .default_value("global"),
@@ -55,6 +161,7 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
         .arg(
             clap::Arg::new("strategy")
                 .long("strategy")
+                .global(true)
                 .about("The alignment strategy to use.")
                 .possible_values(&["sw", "nw"])
                 .default_value("nw"),
@@ -62,62 +169,224 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
         .arg(
             clap::Arg::new("delay_limit")
                 .long("delay_limit")
+                .global(true)
                 .about("Maximum delay between two consecutive s.")
                 .default_value("1800.0"),
         )
+        .arg(
+            clap::Arg::new("segmentation")
+                .long("segmentation")
+                .global(true)
+                .about("Strategy to split the history of a user into traces.")
+                .possible_values(&["gap", "stay_point", "speed_zero", "fixed_window"])
+                .default_value("gap"),
+        )
+        .arg(
+            clap::Arg::new("stay_radius")
+                .long("stay_radius")
+                .global(true)
+                .about("Radius in meters within which the vehicle has to stay to detect a stay point.")
+                .default_value("100.0"),
+        )
+        .arg(
+            clap::Arg::new("stay_duration")
+                .long("stay_duration")
+                .global(true)
+                .about("Minimum duration in seconds of a stay point.")
+                .default_value("300.0"),
+        )
+        .arg(
+            clap::Arg::new("stop_duration")
+                .long("stop_duration")
+                .global(true)
+                .about("Minimum duration in seconds of a standstill to split a trace with the speed_zero strategy.")
+                .default_value("180.0"),
+        )
+        .arg(
+            clap::Arg::new("speed_field")
+                .long("speed_field")
+                .global(true)
+                .about("Attribute holding the speed for the speed_zero strategy.")
+                .default_value("speed"),
+        )
+        .arg(
+            clap::Arg::new("window")
+                .long("window")
+                .global(true)
+                .about("Length in seconds of the time windows of the fixed_window strategy.")
+                .default_value("3600.0"),
+        )
         .arg(
             clap::Arg::new("fields")
                 .long("fields")
-                .possible_values(&["speed", "heading", "street", "postcode", "state", "hour", "day", "highway", "hamlet", "suburb", "village", "location_code"])
-                .about("Data fields to consider for the analysis.")
+                .global(true)
+                .about("Data fields to consider for the analysis. Each field must be an attribute of the schema.")
                 .multiple_values(true)
                 .default_values(&["speed", "street", "postcode", "suburb", "village"])
         )
+        .arg(
+            clap::Arg::new("binning")
+                .long("binning")
+                .global(true)
+                .multiple_values(true)
+                .takes_value(true)
+                .about("Binning of numeric attributes as attribute:strategy:parameter, e.g. speed:fixed_width:10, speed:quantile:8 or heading:circular:8. Overrides the binning of the schema.")
+        )
+        .arg(
+            clap::Arg::new("missing")
+                .long("missing")
+                .global(true)
+                .multiple_values(true)
+                .takes_value(true)
+                .about("Policy for missing values as attribute:policy with policy category, skip, wildcard or non_match, e.g. street:wildcard. Overrides the policy of the schema.")
+        )
         .arg(
             clap::Arg::new("max_trace_len")
                 .long("max_trace_len")
+                .global(true)
                 .default_value("500")
                 .about("Maximum length of a single trace."),
         )
         .arg(
             clap::Arg::new("min_trace_len")
                 .long("min_trace_len")
+                .global(true)
                 .default_value("10")
                 .about("Minimum length of a single trace."),
         )
         .arg(
             clap::Arg::new("max_trace_duration")
                 .long("max_trace_duration")
+                .global(true)
                 .default_value("86400.0")
                 .about("Maximum duration of a single trace."),
         )
         .arg(
             clap::Arg::new("min_num_traces")
                 .long("min_num_traces")
+                .global(true)
                 .default_value("4")
                 .about("Minimum number of traces per user."),
         )
         .arg(
             clap::Arg::new("user_sample_size")
                 .long("user_sample_size")
+                .global(true)
                 .default_value("400")
                 .about("Number of clients to sample."),
         )
         .arg(
             clap::Arg::new("trace_sample_size")
                 .long("trace_sample_size")
+                .global(true)
                 .default_value("500")
                 .about("Number of traces to sample per user"),
         )
         .arg(
             clap::Arg::new("target_trace_sample_size")
                 .long("target_trace_sample_size")
+                .global(true)
                 .default_value("1")
                 .about("Number of target traces per user."),
         )
+        .arg(
+            clap::Arg::new("split")
+                .long("split")
+                .global(true)
+                .about("Strategy to split the history of each user into observed and target traces.")
+                .possible_values(&["ratio", "cutoff", "leave_one_out", "random"])
+                .default_value("ratio"),
+        )
+        .arg(
+            clap::Arg::new("split_ratio")
+                .long("split_ratio")
+                .global(true)
+                .default_value("0.5")
+                .about("Share of the traces of each user that are observed with the ratio and random splits."),
+        )
+        .arg(
+            clap::Arg::new("split_cutoff")
+                .long("split_cutoff")
+                .global(true)
+                .takes_value(true)
+                .about("Date (2021-01-15), date and time (2021-01-15T12:00:00) in the time zone of the dataset or Unix timestamp that separates observed and target traces with the cutoff split."),
+        )
+        .arg(
+            clap::Arg::new("split_gap_days")
+                .long("split_gap_days")
+                .global(true)
+                .default_value("0.0")
+                .about("Minimum number of days between the observed traces and the target traces with the ratio and cutoff splits."),
+        )
+        .arg(
+            clap::Arg::new("stratify")
+                .long("stratify")
+                .global(true)
+                .about("Activity measure by which the users are stratified. The same number of users is sampled from each stratum.")
+                .possible_values(&["none", "num_traces", "distance", "time_span"])
+                .default_value("none"),
+        )
+        .arg(
+            clap::Arg::new("num_strata")
+                .long("num_strata")
+                .global(true)
+                .default_value("4")
+                .about("Number of strata of equal size, from the least to the most active users."),
+        )
+        .arg(
+            clap::Arg::new("open_world_fraction")
+                .long("open_world_fraction")
+                .global(true)
+                .default_value("0.0")
+                .about("Fraction of the target users withheld from the candidates (open world). 0 means all target users are candidates."),
+        )
+        .arg(
+            clap::Arg::new("rejection_threshold")
+                .long("rejection_threshold")
+                .global(true)
+                .takes_value(true)
+                .about("Attacks are rejected if the best candidate has a higher distance (frequency) or a lower score (sequence) than this threshold."),
+        )
+        .arg(
+            clap::Arg::new("det_path")
+                .long("det_path")
+                .global(true)
+                .takes_value(true)
+                .about("Path to a CSV file to write the detection error tradeoff (DET) curve of the open-world mode to."),
+        )
+        .arg(
+            clap::Arg::new("top_k")
+                .long("top_k")
+                .global(true)
+                .multiple_values(true)
+                .default_values(&["1", "5", "10", "20"])
+                .about("Ranks k at which the identification rate is reported in addition to the CMC curve."),
+        )
+        .arg(
+            clap::Arg::new("bootstrap_samples")
+                .long("bootstrap_samples")
+                .global(true)
+                .default_value("1000")
+                .about("Number of bootstrap resamples for confidence intervals and paired tests."),
+        )
+        .arg(
+            clap::Arg::new("ranking_path")
+                .long("ranking_path")
+                .global(true)
+                .takes_value(true)
+                .about("Path to a file to write the ranking of every attack to. Files ending in .jsonl are written as JSON Lines, all others as CSV."),
+        )
+        .arg(
+            clap::Arg::new("ranking_top_n")
+                .long("ranking_top_n")
+                .global(true)
+                .default_value("10")
+                .about("Number of best ranked candidate users recorded per attack."),
+        )
         .arg(
             clap::Arg::new("metric")
                 .long("metric")
+                .global(true)
                 .default_value("euclidean")
                 .about("Distance metric to compare a pair of  traces.")
                 .possible_values(&["euclidean", "manhattan", "cosine", "non_intersection", "bhattacharyya", "kullbrack_leibler", "total_variation", "jeffries_matusita", "chi_quared"]),
@@ -125,34 +394,310 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
         .arg(
             clap::Arg::new("path")
                 .long("path")
+                .global(true)
                 .default_value("/home/john/data/mobility/driving_sampled_8k.csv")
                 .about("Path to the dataset.")
         )
+        .arg(
+            clap::Arg::new("schema")
+                .long("schema")
+                .global(true)
+                .takes_value(true)
+                .about("Path to a JSON file that maps the columns of the dataset to attributes. Defaults to the columns of the original dataset.")
+        )
+        .arg(
+            clap::Arg::new("timezone")
+                .long("timezone")
+                .global(true)
+                .takes_value(true)
+                .about("IANA time zone (e.g. Europe/Berlin) in which hour and day are extracted. Overrides the time zone of the schema.")
+        )
+        .arg(
+            clap::Arg::new("osm")
+                .long("osm")
+                .global(true)
+                .takes_value(true)
+                .about("Path to an OpenStreetMap extract (.osm.pbf or .osm) to snap raw GPS points onto. Required for road attributes.")
+        )
+        .arg(
+            clap::Arg::new("max_snap_distance")
+                .long("max_snap_distance")
+                .global(true)
+                .default_value("50.0")
+                .about("Maximum distance in meters between a GPS point and the road it is snapped onto.")
+        )
+        .arg(
+            clap::Arg::new("sort")
+                .long("sort")
+                .global(true)
+                .default_value("false")
+                .about("Set to true if the records are not grouped by user and sorted by timestamp.")
+        )
+        .arg(
+            clap::Arg::new("sort_buffer_size")
+                .long("sort_buffer_size")
+                .global(true)
+                .default_value("5000000")
+                .about("Number of records to sort in memory before spilling sorted runs to disk.")
+        )
+        .arg(
+            clap::Arg::new("user_id_map")
+                .long("user_id_map")
+                .global(true)
+                .takes_value(true)
+                .about("Path to a CSV file to write the mapping from the sequential user ids to the original user ids to.")
+        )
+        .arg(
+            clap::Arg::new("output")
+                .long("output")
+                .global(true)
+                .default_value("tmp/evaluation")
+                .about("File the scores of every evaluation are appended to. Files ending in .json are written as a JSON array, files ending in .jsonl as JSON Lines, all others as CSV. The generate command requires it as path of the synthetic dataset.")
+        )
+        .arg(
+            clap::Arg::new("store")
+                .long("store")
+                .global(true)
+                .takes_value(true)
+                .about("SQLite database the configuration, scores, rankings and sampling maps of every evaluation are stored in.")
+        )
+        .arg(
+            clap::Arg::new("cache_dir")
+                .long("cache_dir")
+                .global(true)
+                .default_value("tmp/cache")
+                .about("Directory of the binary dataset caches written by the prepare command.")
+        )
+        .arg(
+            clap::Arg::new("cache_content_hash")
+                .long("cache_content_hash")
+                .global(true)
+                .default_value("false")
+                .about("Set to true to key the dataset cache by a hash of the content of the input file instead of its path, size and modification time.")
+        )
+        .arg(
+            clap::Arg::new("path_to_map")
+                .long("path_to_map")
+                .global(true)
+                .takes_value(true)
+                .about("Path to a JSON or pickle (.pkl) file with the sampled target, observed and test traces to replay instead of sampling.")
+        )
+        .arg(
+            clap::Arg::new("save_map")
+                .long("save_map")
+                .global(true)
+                .takes_value(true)
+                .about("Path to a JSON or pickle (.pkl) file to export the sampled target, observed and test traces to.")
+        )
         .arg(
             clap::Arg::new("seed")
                 .long("seed")
+                .global(true)
                 .default_value("0")
                 .about("Random seed for reproducability.")
         )
         .arg(
             clap::Arg::new("typical")
                 .long("typical")
+                .global(true)
                 .default_value("false")
                 .about("Set to true if you want to compute a typical trace (session) per user.")
         )
         .arg(
             clap::Arg::new("dependent")
                 .long("dependent")
+                .global(true)
                 .default_value("false")
                 .about("Set true of the linkage attacks are dependent on each other.")
         )
         .arg(
             clap::Arg::new("reverse")
                 .long("reverse")
+                .global(true)
                 .default_value("false")
                 .about("Set to true if you want to reverse the sequence for pairwise comparison.")
         )
-        .get_matches();
+        .subcommand(
+            clap::App::new("prepare")
+                .about("Parses the dataset and caches the segmented traces for subsequent evaluations.")
+        )
+        .subcommand(
+            clap::App::new("report")
+                .about("Reports the data quality of the dataset after segmentation as JSON and as table.")
+                .arg(
+                    clap::Arg::new("report_path")
+                        .long("report_path")
+                        .default_value("tmp/report.json")
+                        .about("Path of the JSON file to write the report to.")
+                )
+        )
+        .subcommand(
+            clap::App::new("missing_report")
+                .about("Reports how the top-1 accuracy changes with each missing-value policy applied to all fields.")
+        )
+        .subcommand(
+            clap::App::new("repeat")
+                .about("Repeats the sampling and evaluation with consecutive seeds and aggregates the scores with confidence intervals across the repeats.")
+                .arg(
+                    clap::Arg::new("partitions")
+                        .long("partitions")
+                        .default_value("1")
+                        .about("Number of disjoint partitions the sampled target users of a repeat are split into and attacked separately.")
+                )
+                .arg(
+                    clap::Arg::new("repeats")
+                        .long("repeats")
+                        .default_value("5")
+                        .about("Number of repeats with consecutive seeds starting at --seed, at least two.")
+                )
+                .arg(
+                    clap::Arg::new("parallel_partitions")
+                        .long("parallel_partitions")
+                        .default_value("1")
+                        .about("Number of partitions evaluated in parallel. Higher values need more memory.")
+                )
+                .arg(
+                    clap::Arg::new("experiment_path")
+                        .long("experiment_path")
+                        .default_value("tmp/repeat.json")
+                        .about("Path of the JSON file to write the experiment record to.")
+                )
+        )
+        .subcommand(
+            clap::App::new("compare")
+                .about("Compares the rankings of two configurations evaluated on the same sampling maps with McNemar's test and a paired bootstrap test.")
+                .arg(
+                    clap::Arg::new("baseline")
+                        .long("baseline")
+                        .required(true)
+                        .takes_value(true)
+                        .about("Rankings of the baseline configuration written with --ranking_path.")
+                )
+                .arg(
+                    clap::Arg::new("candidate")
+                        .long("candidate")
+                        .required(true)
+                        .takes_value(true)
+                        .about("Rankings of the candidate configuration written with --ranking_path.")
+                )
+                .arg(
+                    clap::Arg::new("comparison_path")
+                        .long("comparison_path")
+                        .default_value("tmp/comparison.json")
+                        .about("Path of the JSON file to write the test results to.")
+                )
+        )
+        .subcommand(
+            clap::App::new("results")
+                .about("Lists, filters, groups and compares the runs in the experiment store given by --store.")
+                .arg(
+                    clap::Arg::new("filter")
+                        .long("filter")
+                        .multiple_values(true)
+                        .takes_value(true)
+                        .about("Configuration values as key=value the listed runs must have, e.g. approach=sequence or repeat.num_repeats=5. Lists are separated by commas.")
+                )
+                .arg(
+                    clap::Arg::new("columns")
+                        .long("columns")
+                        .multiple_values(true)
+                        .default_values(&["approach", "fields", "seed"])
+                        .about("Configuration keys listed for every run.")
+                )
+                .arg(
+                    clap::Arg::new("group_by")
+                        .long("group_by")
+                        .takes_value(true)
+                        .about("Configuration key by which the runs are grouped and their mean scores compared.")
+                )
+                .arg(
+                    clap::Arg::new("diff")
+                        .long("diff")
+                        .number_of_values(2)
+                        .about("Ids (or unique prefixes) of two runs whose configurations and scores are compared.")
+                )
+        )
+        .subcommand(
+            clap::App::new("generate")
+                .about("Generates a synthetic dataset from a population model and writes it to the path given by --output.")
+                .arg(
+                    clap::Arg::new("force")
+                        .long("force")
+                        .about("Overwrite an existing file at the output path.")
+                )
+                .arg(
+                    clap::Arg::new("num_users")
+                        .long("num_users")
+                        .default_value("100")
+                        .about("Number of synthetic users.")
+                )
+                .arg(
+                    clap::Arg::new("num_days")
+                        .long("num_days")
+                        .default_value("28")
+                        .about("Number of days of mobility per user.")
+                )
+                .arg(
+                    clap::Arg::new("num_streets")
+                        .long("num_streets")
+                        .default_value("2000")
+                        .about("Number of streets of the synthetic road network.")
+                )
+                .arg(
+                    clap::Arg::new("num_shared_routes")
+                        .long("num_shared_routes")
+                        .default_value("20")
+                        .about("Number of routes in the pool that users can share.")
+                )
+                .arg(
+                    clap::Arg::new("route_sharing")
+                        .long("route_sharing")
+                        .default_value("0.0")
+                        .about("Probability that the commute of a user is drawn from the pool of shared routes. Higher values make linkage harder.")
+                )
+                .arg(
+                    clap::Arg::new("noise")
+                        .long("noise")
+                        .default_value("0.05")
+                        .about("Probability that a point is geocoded to a random street.")
+                )
+                .arg(
+                    clap::Arg::new("missing_rate")
+                        .long("missing_rate")
+                        .default_value("0.1")
+                        .about("Probability that the street of a point is missing.")
+                )
+                .arg(
+                    clap::Arg::new("sample_interval")
+                        .long("sample_interval")
+                        .default_value("30.0")
+                        .about("Average time in seconds between two consecutive points of a trip.")
+                )
+        )
+        .get_matches_from(args);
+
+    let mut schema = match matches.value_of("schema") {
+        Some(path) => Schema::from_path(path).unwrap(),
+        None => Schema::default(),
+    };
+    for spec in matches.values_of_lossy("binning").unwrap_or_default().iter() {
+        let (name, binning) = match spec.split_once(':') {
+            Some((name, binning)) => (name, Binning::from_spec(binning).unwrap()),
+            None => panic!("Error: Invalid binning {:?}.", spec),
+        };
+        schema.set_binning(name, binning).unwrap();
+    }
+    for spec in matches.values_of_lossy("missing").unwrap_or_default().iter() {
+        let (name, policy) = match spec.split_once(':') {
+            Some((name, policy)) => (name, MissingPolicy::from_str(policy).unwrap()),
+            None => panic!("Error: Invalid missing-value policy {:?}.", spec),
+        };
+        schema.set_missing_policy(name, policy).unwrap();
+    }
+    if let Some(timezone) = matches.value_of("timezone") {
+        schema::parse_timezone(timezone).unwrap();
+        schema.timezone = Some(timezone.to_string());
+    }
 
     let config = Config {
         delay_limit: matches
@@ -173,7 +718,10 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
             .values_of_lossy("fields")
             .unwrap_or_default()
             .iter()
-            .map(|x| DataFields::from_str(x).unwrap())
+            .map(|x| match schema.attribute_idx(x) {
+                Some(idx) => idx,
+                None => panic!("Error: Wrong data field supplied: {:?}", x),
+            })
             .collect(),
         trace_sample_size: matches
             .value_of("trace_sample_size")
@@ -247,6 +795,238 @@ pub fn get_cli_config() -> Result<Config, clap::Error> {
             .value_of("scope")
             .map(String::from)
             .unwrap_or_default(),
+        schema: schema,
+        osm: matches.value_of("osm").map(String::from),
+        max_snap_distance: matches
+            .value_of("max_snap_distance")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        sort: matches
+            .value_of("sort")
+            .unwrap_or_default()
+            .parse::<bool>()
+            .unwrap(),
+        sort_buffer_size: matches
+            .value_of("sort_buffer_size")
+            .unwrap_or_default()
+            .parse::<usize>()
+            .unwrap(),
+        user_id_map: matches.value_of("user_id_map").map(String::from),
+        command: matches.subcommand_name().unwrap_or("evaluate").to_string(),
+        store: matches.value_of("store").map(String::from),
+        missing_policy: None,
+        results: matches.subcommand_matches("results").map(|sub_matches| ResultsConfig {
+            filters: sub_matches
+                .values_of_lossy("filter")
+                .unwrap_or_default()
+                .iter()
+                .map(|x| match x.split_once('=') {
+                    Some((key, value)) => (key.to_string(), value.to_string()),
+                    None => panic!("Error: Wrong filter supplied, expected key=value: {:?}", x),
+                })
+                .collect(),
+            columns: sub_matches.values_of_lossy("columns").unwrap_or_default(),
+            group_by: sub_matches.value_of("group_by").map(String::from),
+            diff: sub_matches
+                .values_of_lossy("diff")
+                .map(|runs| (runs[0].clone(), runs[1].clone())),
+        }),
+        output: matches
+            .value_of("output")
+            .map(String::from)
+            .unwrap_or_default(),
+        run_id: utils::gen_run_id(),
+        timestamp: Utc::now().to_rfc3339(),
+        cache_dir: matches
+            .value_of("cache_dir")
+            .map(String::from)
+            .unwrap_or_default(),
+        cache_content_hash: matches
+            .value_of("cache_content_hash")
+            .unwrap_or_default()
+            .parse::<bool>()
+            .unwrap(),
+        segmentation: matches
+            .value_of("segmentation")
+            .map(String::from)
+            .unwrap_or_default(),
+        stay_radius: matches
+            .value_of("stay_radius")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        stay_duration: matches
+            .value_of("stay_duration")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        stop_duration: matches
+            .value_of("stop_duration")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        speed_field: matches
+            .value_of("speed_field")
+            .map(String::from)
+            .unwrap_or_default(),
+        window: matches
+            .value_of("window")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        report_path: matches
+            .subcommand_matches("report")
+            .and_then(|sub_matches| sub_matches.value_of("report_path"))
+            .map(String::from)
+            .unwrap_or_default(),
+        path_to_map: matches.value_of("path_to_map").map(String::from),
+        save_map: matches.value_of("save_map").map(String::from),
+        split: matches
+            .value_of("split")
+            .map(String::from)
+            .unwrap_or_default(),
+        split_ratio: matches
+            .value_of("split_ratio")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        split_cutoff: matches.value_of("split_cutoff").map(String::from),
+        split_gap_days: matches
+            .value_of("split_gap_days")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        stratify: matches
+            .value_of("stratify")
+            .map(String::from)
+            .unwrap_or_default(),
+        num_strata: matches
+            .value_of("num_strata")
+            .unwrap_or_default()
+            .parse::<usize>()
+            .unwrap(),
+        open_world_fraction: matches
+            .value_of("open_world_fraction")
+            .unwrap_or_default()
+            .parse::<f64>()
+            .unwrap(),
+        rejection_threshold: matches
+            .value_of("rejection_threshold")
+            .map(|threshold| threshold.parse::<f64>().unwrap()),
+        det_path: matches.value_of("det_path").map(String::from),
+        ranking_path: matches.value_of("ranking_path").map(String::from),
+        top_k: matches
+            .values_of_lossy("top_k")
+            .unwrap_or_default()
+            .iter()
+            .map(|x| match usize::from_str(x) {
+                Ok(k) => k,
+                _ => panic!("Error: Wrong rank supplied: {:?}", x),
+            })
+            .collect(),
+        bootstrap_samples: matches
+            .value_of("bootstrap_samples")
+            .unwrap_or_default()
+            .parse::<usize>()
+            .unwrap(),
+        comparison: matches.subcommand_matches("compare").map(|sub_matches| ComparisonConfig {
+            baseline_path: sub_matches
+                .value_of("baseline")
+                .map(String::from)
+                .unwrap_or_default(),
+            candidate_path: sub_matches
+                .value_of("candidate")
+                .map(String::from)
+                .unwrap_or_default(),
+            comparison_path: sub_matches
+                .value_of("comparison_path")
+                .map(String::from)
+                .unwrap_or_default(),
+        }),
+        ranking_top_n: matches
+            .value_of("ranking_top_n")
+            .unwrap_or_default()
+            .parse::<usize>()
+            .unwrap(),
+        repeat: matches.subcommand_matches("repeat").map(|sub_matches| RepeatConfig {
+            num_partitions: sub_matches
+                .value_of("partitions")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap(),
+            num_repeats: sub_matches
+                .value_of("repeats")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap(),
+            parallel_partitions: sub_matches
+                .value_of("parallel_partitions")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap(),
+            experiment_path: sub_matches
+                .value_of("experiment_path")
+                .map(String::from)
+                .unwrap_or_default(),
+        }),
+        generator: matches.subcommand_matches("generate").map(|sub_matches| GeneratorConfig {
+            // The default of --output is the evaluation file, so the path of the dataset has to be given explicitly
+            output: match sub_matches.occurrences_of("output") {
+                0 => panic!("Error: The generate command requires the path of the synthetic dataset as --output."),
+                _ => sub_matches.value_of("output").map(String::from).unwrap_or_default(),
+            },
+            force: sub_matches.is_present("force"),
+            num_users: sub_matches
+                .value_of("num_users")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap(),
+            num_days: sub_matches
+                .value_of("num_days")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap(),
+            num_streets: sub_matches
+                .value_of("num_streets")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap(),
+            num_shared_routes: sub_matches
+                .value_of("num_shared_routes")
+                .unwrap_or_default()
+                .parse::<usize>()
+                .unwrap(),
+            route_sharing: sub_matches
+                .value_of("route_sharing")
+                .unwrap_or_default()
+                .parse::<f64>()
+                .unwrap(),
+            noise: sub_matches
+                .value_of("noise")
+                .unwrap_or_default()
+                .parse::<f64>()
+                .unwrap(),
+            missing_rate: sub_matches
+                .value_of("missing_rate")
+                .unwrap_or_default()
+                .parse::<f64>()
+                .unwrap(),
+            sample_interval: sub_matches
+                .value_of("sample_interval")
+                .unwrap_or_default()
+                .parse::<f64>()
+                .unwrap(),
+        }),
     };
+    if !(0.0..1.0).contains(&config.open_world_fraction) {
+        panic!("Error: The open-world fraction must be at least 0 and less than 1.");
+    }
+    if config.bootstrap_samples == 0 {
+        panic!("Error: The number of bootstrap samples must be positive.");
+    }
+    if config.top_k.contains(&0) {
+        panic!("Error: The ranks given by --top_k must be positive.");
+    }
     Ok(config)
 }
@@ -0,0 +1,122 @@
+use crate::cli::Config;
+use crate::ranking::{self, Outcome};
+use crate::stats::{self, McNemar, PairedBootstrap};
+
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::Path,
+};
+
+/// Name of a score with the test whether an attack succeeds under it.
+type Score = (String, Box<dyn Fn(&Outcome) -> bool>);
+
+/// Paired tests of a single score.
+#[derive(Debug, Serialize)]
+pub struct PairedTest {
+    pub score: String,
+    pub baseline: f64,
+    pub candidate: f64,
+    pub mcnemar: McNemar,
+    pub bootstrap: PairedBootstrap,
+}
+
+/// Record of the comparison of two configurations on the same attacks.
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub baseline: String,
+    pub candidate: String,
+    /// Number of attacks on present target users, which are the pairs the tests are based on
+    pub num_attacks: usize,
+    pub tests: Vec<PairedTest>,
+}
+
+impl Comparison {
+    /// Writes the comparison record to a JSON file.
+    pub fn write_to_path(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+/// Reads the outcomes of a rankings file by target user.
+fn read_outcomes(path: &str) -> Result<BTreeMap<String, Outcome>, Box<dyn Error>> {
+    let mut user_to_outcome_map: BTreeMap<String, Outcome> = BTreeMap::new();
+    for outcome in ranking::read_from_path(path)? {
+        if user_to_outcome_map.contains_key(&outcome.user_id) {
+            return Err(format!("Error: The target user {:?} is attacked twice in {:?}.", outcome.user_id, path).into());
+        }
+        user_to_outcome_map.insert(outcome.user_id.clone(), outcome);
+    }
+    Ok(user_to_outcome_map)
+}
+
+/// Compares the top-k scores of two configurations with McNemar's test and a paired bootstrap test. The attacks are
+/// paired by target user, so both configurations have to be evaluated on the same sampling maps, e.g. by exporting
+/// them with `--save_map` and loading them with `--path_to_map`.
+pub fn compare(config: &Config) -> Result<Comparison, Box<dyn Error>> {
+    let params = config.comparison.as_ref().unwrap();
+    let baseline = read_outcomes(&params.baseline_path)?;
+    let candidate = read_outcomes(&params.candidate_path)?;
+    let is_paired = baseline.len() == candidate.len()
+        && baseline.iter().all(|(user, outcome)| match candidate.get(user) {
+            Some(other) => other.is_present == outcome.is_present,
+            None => false,
+        });
+    if !is_paired {
+        return Err("Error: The rankings do not cover the same target users. Evaluate both configurations on the same sampling maps.".into());
+    }
+
+    let pairs: Vec<(&Outcome, &Outcome)> = baseline
+        .iter()
+        .filter(|(_, outcome)| outcome.is_present)
+        .map(|(user, outcome)| (outcome, &candidate[user]))
+        .collect();
+    let mut scores: Vec<Score> = vec![
+        ("top_1".to_string(), Box::new(|outcome: &Outcome| outcome.is_top_1)),
+        ("top_10".to_string(), Box::new(|outcome: &Outcome| outcome.is_top_10)),
+        ("top_10_percent".to_string(), Box::new(|outcome: &Outcome| outcome.is_top_10_percent)),
+    ];
+    for k in config.top_k.iter().cloned() {
+        scores.push((
+            format!("rank_{}", k),
+            Box::new(move |outcome: &Outcome| outcome.rank.is_some_and(|rank| rank <= k)),
+        ));
+    }
+
+    // Resample with a fixed seed so that the tests are reproducible
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let tests: Vec<PairedTest> = scores
+        .iter()
+        .map(|(score, is_success)| {
+            let baseline_outcomes: Vec<bool> = pairs.iter().map(|(outcome, _)| is_success(outcome)).collect();
+            let candidate_outcomes: Vec<bool> = pairs.iter().map(|(_, outcome)| is_success(outcome)).collect();
+            PairedTest {
+                score: score.clone(),
+                baseline: stats::rate(&baseline_outcomes),
+                candidate: stats::rate(&candidate_outcomes),
+                mcnemar: stats::mcnemar(&baseline_outcomes, &candidate_outcomes),
+                bootstrap: stats::paired_bootstrap(
+                    &baseline_outcomes,
+                    &candidate_outcomes,
+                    config.bootstrap_samples,
+                    &mut rng,
+                ),
+            }
+        })
+        .collect();
+
+    Ok(Comparison {
+        baseline: params.baseline_path.clone(),
+        candidate: params.candidate_path.clone(),
+        num_attacks: pairs.len(),
+        tests: tests,
+    })
+}
@@ -1,11 +1,6 @@
 use nalgebra::MatrixXx1;
 use num_traits::ToPrimitive;
 
-/// Creates vector of u32 zeros
-pub fn zeros_u32(size: usize) -> Vec<u32> {
-    vec![0; size]
-}
-
 /// Creates vector of f64 zeros
 pub fn zeros_f64(size: usize) -> Vec<f64> {
     vec![0.0; size]
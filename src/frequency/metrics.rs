@@ -43,8 +43,7 @@ where
 {
     let target_matrix = maths::vec_to_matrix(target_vec, true);
     let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let dist = target_matrix.apply_metric_distance(&ref_matrix, &EuclideanNorm);
-    dist
+    target_matrix.apply_metric_distance(&ref_matrix, &EuclideanNorm)
 }
 
 pub fn manhattan_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
@@ -54,8 +53,7 @@ where
 {
     let target_matrix = maths::vec_to_matrix(target_vec, true);
     let ref_matrix = maths::vec_to_matrix(ref_vec, true);
-    let dist = target_matrix.apply_metric_distance(&ref_matrix, &LpNorm(1));
-    dist
+    target_matrix.apply_metric_distance(&ref_matrix, &LpNorm(1))
 }
 
 pub fn consine_dist<T, U>(target_vec: Vec<T>, ref_vec: Vec<U>) -> f64
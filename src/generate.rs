@@ -0,0 +1,344 @@
+use crate::cli::GeneratorConfig;
+
+use csv::Writer;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::{
+    error::Error,
+    f64::consts::PI,
+    fs::OpenOptions,
+    io::ErrorKind,
+};
+
+/// Start of the generated period: Monday, 4 January 2021, 00:00 UTC.
+const START_TIME: f64 = 1_609_718_400.0;
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Center and extent in degrees of the synthetic city.
+const CENTER: (f64, f64) = (48.137, 11.575);
+const EXTENT: (f64, f64) = (0.09, 0.135);
+
+/// Number of districts along each axis of the city.
+const NUM_DISTRICTS: usize = 4;
+
+/// Road types and their typical speed in km/h.
+const HIGHWAYS: &[(&str, f64)] = &[
+    ("residential", 30.0),
+    ("tertiary", 50.0),
+    ("secondary", 70.0),
+    ("primary", 100.0),
+    ("motorway", 130.0),
+];
+
+/// Columns of the generated dataset, which match the default schema plus the raw coordinates.
+const HEADERS: &[&str] = &[
+    "user_id",
+    "timestamp",
+    "heading",
+    "speed",
+    "street",
+    "postcode",
+    "state",
+    "highway",
+    "hamlet",
+    "suburb",
+    "village",
+    "location_code",
+    "lat",
+    "lon",
+];
+
+/// A street of the synthetic road network along with its geocoded attributes.
+struct Street {
+    name: String,
+    postcode: String,
+    state: String,
+    highway: usize,
+    hamlet: String,
+    suburb: String,
+    village: String,
+    location_code: String,
+    start: (f64, f64),
+    /// Direction of the street in degrees
+    heading: f64,
+}
+
+/// Sequence of streets driven from an origin to a destination.
+#[derive(Clone)]
+struct Route {
+    streets: Vec<usize>,
+}
+
+/// A synthetic user with a commute between home and work and habits for the time of day.
+struct User {
+    id: String,
+    commute: Route,
+    leisure_routes: Vec<Route>,
+    /// Hour of the day at which the user usually leaves for work
+    departure_hour: f64,
+    /// Hour of the day at which the user usually returns home
+    return_hour: f64,
+    /// Hour of the day at which the user usually starts a trip on weekends
+    leisure_hour: f64,
+    /// Standard deviation in hours of the departure times
+    punctuality: f64,
+    /// Probability of a trip on a day of the weekend
+    leisure_prob: f64,
+}
+
+/// Draws a sample from a normal distribution with the Box-Muller transform.
+fn normal<R: Rng>(rng: &mut R, mean: f64, std: f64) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen_range(0.0..1.0);
+    mean + std * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+/// Generates the road network of a city that is divided into districts with their own postcode and suburb.
+fn gen_streets<R: Rng>(rng: &mut R, num_streets: usize) -> Vec<Street> {
+    (0..num_streets)
+        .map(|idx| {
+            let lat = CENTER.0 + rng.gen_range(-EXTENT.0..EXTENT.0);
+            let lon = CENTER.1 + rng.gen_range(-EXTENT.1..EXTENT.1);
+            let row = (((lat - CENTER.0 + EXTENT.0) / (2.0 * EXTENT.0)) * NUM_DISTRICTS as f64) as usize;
+            let col = (((lon - CENTER.1 + EXTENT.1) / (2.0 * EXTENT.1)) * NUM_DISTRICTS as f64) as usize;
+            let district = row.min(NUM_DISTRICTS - 1) * NUM_DISTRICTS + col.min(NUM_DISTRICTS - 1);
+            let is_outskirts =
+                row == 0 || col == 0 || row >= NUM_DISTRICTS - 1 || col >= NUM_DISTRICTS - 1;
+            Street {
+                name: format!("Street {}", idx),
+                postcode: format!("80{:03}", district * 10 + idx % 3),
+                state: "Bavaria".to_string(),
+                highway: rng.gen_range(0..HIGHWAYS.len()),
+                hamlet: if rng.gen_bool(0.1) {
+                    format!("Hamlet {}", idx % 50)
+                } else {
+                    String::new()
+                },
+                suburb: if is_outskirts {
+                    String::new()
+                } else {
+                    format!("Suburb {}", district)
+                },
+                village: if is_outskirts {
+                    format!("Village {}", district)
+                } else {
+                    String::new()
+                },
+                location_code: format!("LC{:02}", district),
+                start: (lat, lon),
+                heading: (rng.gen_range(0..36) * 10) as f64,
+            }
+        })
+        .collect()
+}
+
+/// Returns the index of the street that starts closest to the given point.
+fn nearest_street(streets: &[Street], point: (f64, f64)) -> usize {
+    let dist = |street: &Street| (street.start.0 - point.0).powi(2) + (street.start.1 - point.1).powi(2);
+    (0..streets.len())
+        .min_by(|a, b| dist(&streets[*a]).partial_cmp(&dist(&streets[*b])).unwrap())
+        .unwrap()
+}
+
+/// Generates a route between two streets that passes the streets closest to the straight line between them.
+fn gen_route<R: Rng>(rng: &mut R, streets: &[Street], origin: usize, destination: usize) -> Route {
+    let num_streets: usize = rng.gen_range(4..12);
+    let (start, end) = (streets[origin].start, streets[destination].start);
+    let mut route = vec![origin];
+    for step in 1..num_streets - 1 {
+        let frac = step as f64 / (num_streets - 1) as f64;
+        let point = (
+            start.0 + frac * (end.0 - start.0) + normal(rng, 0.0, 0.002),
+            start.1 + frac * (end.1 - start.1) + normal(rng, 0.0, 0.003),
+        );
+        let street = nearest_street(streets, point);
+        if *route.last().unwrap() != street {
+            route.push(street);
+        }
+    }
+    if *route.last().unwrap() != destination {
+        route.push(destination);
+    }
+    Route { streets: route }
+}
+
+fn reverse_route(route: &Route) -> Route {
+    Route {
+        streets: route.streets.iter().rev().cloned().collect(),
+    }
+}
+
+/// Generates a population of users. Commutes are drawn from the pool of shared routes with the configured probability,
+/// which makes users harder to tell apart.
+fn gen_users<R: Rng>(rng: &mut R, params: &GeneratorConfig, streets: &[Street]) -> Vec<User> {
+    let shared_routes: Vec<Route> = (0..params.num_shared_routes)
+        .map(|_| {
+            let origin = rng.gen_range(0..streets.len());
+            let destination = rng.gen_range(0..streets.len());
+            gen_route(rng, streets, origin, destination)
+        })
+        .collect();
+
+    (0..params.num_users)
+        .map(|idx| {
+            let home = rng.gen_range(0..streets.len());
+            let commute = if !shared_routes.is_empty() && rng.gen_bool(params.route_sharing) {
+                shared_routes.choose(rng).unwrap().clone()
+            } else {
+                let work = rng.gen_range(0..streets.len());
+                gen_route(rng, streets, home, work)
+            };
+            let num_leisure_routes = rng.gen_range(1..4);
+            let leisure_routes = (0..num_leisure_routes)
+                .map(|_| {
+                    let destination = rng.gen_range(0..streets.len());
+                    gen_route(rng, streets, commute.streets[0], destination)
+                })
+                .collect();
+            User {
+                id: format!("user_{:05}", idx),
+                commute: commute,
+                leisure_routes: leisure_routes,
+                departure_hour: normal(rng, 7.5, 1.0).clamp(4.0, 11.0),
+                return_hour: normal(rng, 17.0, 1.0).clamp(13.0, 21.0),
+                leisure_hour: normal(rng, 13.0, 2.5).clamp(8.0, 20.0),
+                punctuality: rng.gen_range(0.1..0.5),
+                leisure_prob: rng.gen_range(0.2..0.9),
+            }
+        })
+        .collect()
+}
+
+/// Writes the points of a single trip along the route starting at the given time. Returns the end time of the trip.
+///
+/// With the configured noise, the geocoded attributes of a point are replaced by those of a random street. With the
+/// configured missing rate, the street of a point is left empty.
+fn write_trip<R: Rng>(
+    rng: &mut R,
+    params: &GeneratorConfig,
+    streets: &[Street],
+    user: &User,
+    route: &Route,
+    start_time: f64,
+    wtr: &mut Writer<std::fs::File>,
+) -> Result<f64, Box<dyn Error>> {
+    let mut time = start_time;
+    for street_idx in route.streets.iter() {
+        let num_points: usize = rng.gen_range(2..9);
+        for point_idx in 0..num_points {
+            let street = if rng.gen_bool(params.noise) {
+                &streets[rng.gen_range(0..streets.len())]
+            } else {
+                &streets[*street_idx]
+            };
+            let (highway, max_speed) = HIGHWAYS[street.highway];
+            let speed = (max_speed * rng.gen_range(0.5..1.0) / 10.0).round() * 10.0;
+            let heading = (street.heading + normal(rng, 0.0, 5.0)).rem_euclid(360.0).round();
+            let progress = point_idx as f64 * 0.001;
+            let lat = street.start.0 + progress * street.heading.to_radians().cos() + normal(rng, 0.0, 0.00005);
+            let lon = street.start.1 + progress * street.heading.to_radians().sin() + normal(rng, 0.0, 0.00005);
+            let name = if rng.gen_bool(params.missing_rate) {
+                ""
+            } else {
+                street.name.as_str()
+            };
+            wtr.write_record([
+                user.id.as_str(),
+                &format!("{}", time.round() as i64),
+                &heading.to_string(),
+                &speed.to_string(),
+                name,
+                &street.postcode,
+                &street.state,
+                highway,
+                &street.hamlet,
+                &street.suburb,
+                &street.village,
+                &street.location_code,
+                &format!("{:.6}", lat),
+                &format!("{:.6}", lon),
+            ])?;
+            time += params.sample_interval * rng.gen_range(0.8..1.2);
+        }
+    }
+    Ok(time)
+}
+
+/// Generates a synthetic mobility dataset from a parametric population model and writes it as CSV file to the
+/// configured output path. An existing file is only overwritten if forced.
+///
+/// Every user commutes between home and work on weekdays at habitual times of day and makes leisure trips on
+/// weekends. The records are grouped by user and sorted by timestamp.
+pub fn generate(seed: u64, params: &GeneratorConfig) -> Result<(), Box<dyn Error>> {
+    for (name, prob) in [
+        ("route_sharing", params.route_sharing),
+        ("noise", params.noise),
+        ("missing_rate", params.missing_rate),
+    ]
+    .iter()
+    {
+        if !(0.0..=1.0).contains(prob) {
+            return Err(format!("Error: {} must be a probability, got {:?}.", name, prob).into());
+        }
+    }
+    if params.num_streets == 0 || params.sample_interval <= 0.0 {
+        return Err("Error: The road network needs streets and the sample interval must be positive.".into());
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let streets = gen_streets(&mut rng, params.num_streets);
+    let users = gen_users(&mut rng, params, &streets);
+
+    let file = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(params.force)
+        .create_new(!params.force)
+        .open(&params.output)
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            return Err(format!("Error: {:?} already exists. Use --force to overwrite it.", params.output).into())
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let mut wtr = Writer::from_writer(file);
+    wtr.write_record(HEADERS)?;
+    let mut num_trips: usize = 0;
+    for user in users.iter() {
+        for day in 0..params.num_days {
+            let midnight = START_TIME + day as f64 * SECONDS_PER_DAY;
+            let mut trips: Vec<(f64, Route)> = Vec::with_capacity(2);
+            if day % 7 < 5 {
+                trips.push((
+                    normal(&mut rng, user.departure_hour, user.punctuality),
+                    user.commute.clone(),
+                ));
+                trips.push((
+                    normal(&mut rng, user.return_hour, 2.0 * user.punctuality),
+                    reverse_route(&user.commute),
+                ));
+            } else if rng.gen_bool(user.leisure_prob) {
+                let route = user.leisure_routes.choose(&mut rng).unwrap();
+                let hour = normal(&mut rng, user.leisure_hour, 1.5);
+                trips.push((hour, route.clone()));
+                trips.push((hour + rng.gen_range(1.0..4.0), reverse_route(route)));
+            }
+
+            let mut end_time = midnight;
+            for (hour, route) in trips.iter() {
+                // Trips never overlap, even if the user leaves earlier than usual
+                let start_time = (midnight + hour * 3600.0).max(end_time + 600.0);
+                end_time = write_trip(&mut rng, params, &streets, user, route, start_time, &mut wtr)?;
+                num_trips += 1;
+            }
+        }
+    }
+    wtr.flush()?;
+    log::info!(
+        "Generated {:?} trips of {:?} users.",
+        num_trips,
+        users.len()
+    );
+    Ok(())
+}
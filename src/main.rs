@@ -1,16 +1,46 @@
+// Struct fields are initialized explicitly throughout, e.g. `scores: scores`
+#![allow(clippy::redundant_field_names)]
+
+mod binning;
+mod cache;
 mod cli;
+mod compare;
 mod frequency;
+mod generate;
+mod osm;
+mod openworld;
 mod parse;
+mod ranking;
+mod reader;
+mod repeat;
+mod report;
+mod results;
 mod sample;
+mod schema;
+mod segment;
 mod sequence;
+mod sort;
+mod spatial;
+mod split;
+mod stats;
+mod store;
+mod strata;
+mod trace;
+mod users;
 mod utils;
 
-use frequency::trace::FreqTrace;
-use sequence::trace::SeqTrace;
+use frequency::trace::{FreqTrace, Histogram};
+use schema::{MissingPolicy, MISSING_POLICIES};
+use parse::Dataset;
+use sample::SamplingMaps;
+use sequence::trace::{SeqTrace, Sequence};
 use simple_logger::SimpleLogger;
+use trace::{Column, Trace};
+use users::UserIds;
+use utils::Scores;
 
 use rand::{rngs::StdRng, SeedableRng};
-use std::collections::{BTreeMap, HashMap};
+use std::{collections::BTreeMap, error::Error};
 
 fn main() {
     // Load config
@@ -23,85 +53,417 @@ fn main() {
         .init()
         .unwrap();
 
+    match config.command.as_str() {
+        "prepare" => prepare(&config),
+        "report" => report(&config),
+        "missing_report" => report_missing_policies(&config),
+        "repeat" => repeat(&config),
+        "compare" => compare(&config),
+        "results" => results::results(&config).unwrap(),
+        "generate" => {
+            let params = config.generator.as_ref().unwrap();
+            generate::generate(config.seed, params).expect("Error generating dataset.");
+            log::info!("Wrote synthetic dataset to {:?}.", params.output);
+        }
+        _ => {
+            evaluate(&config, None);
+        }
+    }
+}
+
+/// Sampling maps with the original user ids along with the number of traces of every user they were sampled from.
+/// The maps can be reused on another parse of the dataset as long as the users and their traces stay the same.
+struct SharedSampling {
+    maps: SamplingMaps<String>,
+    user_to_num_traces_map: BTreeMap<String, usize>,
+}
+
+/// Outcome of an evaluation run.
+struct Evaluation {
+    scores: Scores,
+    user_ids: UserIds,
+    sampling: SharedSampling,
+}
+
+/// Parses (or loads) the data, samples the users and traces and conducts the linkage attacks. Returns the accuracy
+/// scores, which are also appended to the evaluation file, along with the sampling maps. Given shared sampling maps,
+/// these are reused instead of sampling anew if the users and their traces are the same.
+fn evaluate(config: &cli::Config, shared: Option<&SharedSampling>) -> Evaluation {
     // Set random seed for reproducability
     let mut rng = StdRng::seed_from_u64(config.seed);
 
     // Approach 1: Sequence alignment-based
-    if config.approach == "sequence" {
+    let (scores, user_ids, stored_maps, sampling) = if config.approach == "sequence" {
         log::info!("Parsing data for sequence alignment-based approach...");
-        let user_to_seq_map: BTreeMap<u32, Vec<SeqTrace>> =
-            parse::parse_to_sequence(&config).unwrap();
-
-        log::info!("Sampling users...");
-        let user_to_target_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_target_idx_map(
-                &user_to_seq_map,
-                &mut rng,
-                config.user_sample_size,
-                config.target_trace_sample_size,
-            );
+        let dataset: Dataset<Sequence> =
+            cache::load_or_parse(config, parse::parse_to_sequence).unwrap();
+        write_user_id_map(config, &dataset.user_ids, dataset.user_to_traces_map.keys());
+        let mut user_to_seq_map: BTreeMap<u32, Vec<SeqTrace>> = dataset.user_to_traces_map;
 
-        log::info!("Sampling traces per user...");
-        let user_to_sample_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_sample_idx_map(
-                &user_to_seq_map,
-                &mut rng,
-                config.trace_sample_size,
-            );
-
-        // Optional
-        // let serialized_user_to_test_idx_map = fs::read(&config.path_to_map).unwrap();
-        // let user_to_test_idx_map: HashMap<u32, usize> =
-        //     serde_pickle::from_slice(&serialized_user_to_test_idx_map, Default::default())
-        //         .unwrap();
-
-        log::info!("Sampling a single test traces per user...");
-        let user_to_test_idx_map: HashMap<u32, usize> =
-            sample::gen_user_to_test_idx_map(&user_to_sample_idx_map, &mut rng);
+        let (maps, sampling) =
+            gen_sampling_maps(config, &mut user_to_seq_map, &dataset.user_ids, shared, &mut rng).unwrap();
+        let stored_maps = config.store.as_ref().map(|_| maps.clone());
+        let (user_to_target_idx_map, user_to_sample_idx_map, user_to_test_idx_map, stratification) =
+            maps.into_maps();
 
         log::info!("Starting the evaluation...");
-        sequence::evaluation::eval(
-            &config,
+        let mut scores = sequence::evaluation::eval(
+            config,
             &user_to_seq_map,
             &user_to_target_idx_map,
             &user_to_sample_idx_map,
             &user_to_test_idx_map,
         );
+        scores.add_strata(stratification.as_ref());
+        (scores, dataset.user_ids, stored_maps, sampling)
 
     // Approach 2: Frequency-based
     } else {
         log::info!("Parsing data for frequency-based approach...");
-        let user_to_freq_map: BTreeMap<u32, Vec<FreqTrace>> =
-            parse::parse_to_frequency(&config).unwrap();
-
-        log::info!("Sampling users...");
-        let user_to_target_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_target_idx_map(
-                &user_to_freq_map,
-                &mut rng,
-                config.user_sample_size,
-                config.target_trace_sample_size,
-            );
+        let dataset: Dataset<Histogram> =
+            cache::load_or_parse(config, parse::parse_to_frequency).unwrap();
+        write_user_id_map(config, &dataset.user_ids, dataset.user_to_traces_map.keys());
+        let mut user_to_freq_map: BTreeMap<u32, Vec<FreqTrace>> = dataset.user_to_traces_map;
 
-        log::info!("Sampling traces per user...");
-        let user_to_sample_idx_map: HashMap<u32, Vec<usize>> =
-            sample::gen_user_to_sample_idx_map(
-                &user_to_freq_map,
-                &mut rng,
-                config.trace_sample_size,
-            );
-
-        log::info!("Sampling a single test trace per user...");
-        let user_to_test_idx_map: HashMap<u32, usize> =
-            sample::gen_user_to_test_idx_map(&user_to_sample_idx_map, &mut rng);
+        let (maps, sampling) =
+            gen_sampling_maps(config, &mut user_to_freq_map, &dataset.user_ids, shared, &mut rng).unwrap();
+        let stored_maps = config.store.as_ref().map(|_| maps.clone());
+        let (user_to_target_idx_map, user_to_sample_idx_map, user_to_test_idx_map, stratification) =
+            maps.into_maps();
 
         log::info!("Starting the evaluation...");
-        frequency::evaluation::eval(
-            &config,
+        let mut scores = frequency::evaluation::eval(
+            config,
             &user_to_freq_map,
             &user_to_target_idx_map,
             &user_to_sample_idx_map,
             &user_to_test_idx_map,
         );
+        scores.add_strata(stratification.as_ref());
+        (scores, dataset.user_ids, stored_maps, sampling)
+    };
+
+    log::info!("Mean reciprocal rank: {:?}", scores.mean_reciprocal_rank);
+    log::info!("Median rank: {:?}", scores.median_rank);
+    let confidence_intervals = [
+        ("Rank 1", &scores.top_1_ci),
+        ("Top 10", &scores.top_10_ci),
+        ("Top 10 Percent", &scores.top_10_percent_ci),
+    ];
+    for (name, intervals) in confidence_intervals.iter() {
+        log::info!(
+            "{} 95% CI: Wilson [{:.4}, {:.4}], bootstrap [{:.4}, {:.4}]",
+            name,
+            intervals.wilson.lower,
+            intervals.wilson.upper,
+            intervals.bootstrap.lower,
+            intervals.bootstrap.upper
+        );
+    }
+    for (k, rate) in scores.top_k.iter() {
+        log::info!("Identification rate at rank {:?}: {:?}", k, rate);
+    }
+
+    if let Some(path) = &config.ranking_path {
+        ranking::write_to_path(path, &user_ids, &scores.attacks).expect("Error writing rankings.");
+        log::info!("Wrote rankings of {:?} attacks to {:?}.", scores.attacks.len(), path);
+    }
+
+    if !scores.strata.is_empty() {
+        log::info!(
+            "{:<8} {:>14} {:>14} {:>8} {:>8} {:>8}",
+            "stratum", "lower", "upper", "attacks", "top_1", "top_10"
+        );
+        for stratum in scores.strata.iter() {
+            log::info!(
+                "{:<8} {:>14.1} {:>14.1} {:>8} {:>8.4} {:>8.4}",
+                stratum.stratum,
+                stratum.lower,
+                stratum.upper,
+                stratum.num_attacks,
+                stratum.top_1,
+                stratum.top_10
+            );
+        }
+    }
+
+    if let Some(open_world) = &scores.open_world {
+        log::info!(
+            "Present targets: {:?}, absent targets: {:?}",
+            open_world.num_present,
+            open_world.num_absent
+        );
+        log::info!("True match rate: {:?}", open_world.true_match_rate);
+        log::info!("False match rate: {:?}", open_world.false_match_rate);
+        log::info!("Rejection accuracy: {:?}", open_world.rejection_accuracy);
+        log::info!("Equal error rate: {:?}", open_world.equal_error_rate);
+        if let Some(path) = &config.det_path {
+            open_world
+                .write_det_to_path(path)
+                .expect("Error writing DET curve.");
+            log::info!("Wrote DET curve to {:?}.", path);
+        }
+    }
+
+    // Write metrics to final evaluation file
+    utils::write_to_file(config, &scores).expect("Error writing to evaluation file.");
+    if let Some(path) = &config.store {
+        let mut store = store::Store::open(path).expect("Error opening experiment store.");
+        store
+            .insert_run(config, &scores, &user_ids, stored_maps.as_ref())
+            .expect("Error writing to experiment store.");
+        log::info!("Stored run {} in {:?}.", config.run_id, path);
+    }
+    Evaluation {
+        scores: scores,
+        user_ids: user_ids,
+        sampling: sampling,
+    }
+}
+
+/// Samples the users and traces of the linkage attacks, or loads the sampling maps from file if requested, and
+/// exports the maps if requested. Loaded maps make it possible to replay the exact same attacks. Shared maps are
+/// reused if they were sampled from the same users with the same number of traces each.
+///
+/// Users without observed traces under the split cannot be compared to and are removed from the traces.
+fn gen_sampling_maps<C: Column>(
+    config: &cli::Config,
+    user_to_vector_map: &mut BTreeMap<u32, Vec<Trace<C>>>,
+    user_ids: &UserIds,
+    shared: Option<&SharedSampling>,
+    rng: &mut StdRng,
+) -> Result<(SamplingMaps, SharedSampling), Box<dyn Error>> {
+    let user_to_num_traces_map: BTreeMap<String, usize> = user_to_vector_map
+        .iter()
+        .map(|(user, traces)| (user_ids.original(*user).to_string(), traces.len()))
+        .collect();
+    let maps = match (shared, &config.path_to_map) {
+        (Some(shared), _) if shared.user_to_num_traces_map == user_to_num_traces_map => {
+            log::info!("Reusing the shared sampling maps...");
+            SamplingMaps::from_original(&shared.maps, user_ids)?
+        }
+        (Some(_), Some(path)) => {
+            log::warn!("The users or their traces changed, so the shared sampling maps cannot be reused.");
+            log::info!("Loading sampling maps from {:?}...", path);
+            let maps = SamplingMaps::from_path(path, user_ids)?;
+            maps.validate(user_to_vector_map, user_ids)?;
+            maps
+        }
+        (Some(_), None) => {
+            log::warn!("The users or their traces changed, so the shared sampling maps cannot be reused.");
+            SamplingMaps::sample(config, user_to_vector_map, rng)?
+        }
+        (None, Some(path)) => {
+            log::info!("Loading sampling maps from {:?}...", path);
+            let maps = SamplingMaps::from_path(path, user_ids)?;
+            maps.validate(user_to_vector_map, user_ids)?;
+            maps
+        }
+        (None, None) => SamplingMaps::sample(config, user_to_vector_map, rng)?,
+    };
+    maps.retain_observed_users(user_to_vector_map);
+
+    if let Some(path) = &config.save_map {
+        log::info!("Writing sampling maps to {:?}...", path);
+        maps.write_to_path(path, user_ids)?;
+    }
+    let sampling = SharedSampling {
+        maps: maps.to_original(user_ids)?,
+        user_to_num_traces_map: user_to_num_traces_map,
+    };
+    Ok((maps, sampling))
+}
+
+/// Compares the rankings of two configurations with paired significance tests and writes the test results.
+fn compare(config: &cli::Config) {
+    let comparison = compare::compare(config).unwrap();
+    log::info!(
+        "{:<16} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        "score", "baseline", "candidate", "difference", "mcnemar_p", "bootstrap_p"
+    );
+    for test in comparison.tests.iter() {
+        log::info!(
+            "{:<16} {:>8.4} {:>8.4} {:>10.4} {:>10.4} {:>10.4}",
+            test.score,
+            test.baseline,
+            test.candidate,
+            test.bootstrap.difference,
+            test.mcnemar.p_value,
+            test.bootstrap.p_value
+        );
+    }
+    let path = &config.comparison.as_ref().unwrap().comparison_path;
+    comparison
+        .write_to_path(path)
+        .expect("Error writing comparison record.");
+    log::info!("Wrote comparison of {:?} attacks to {:?}.", comparison.num_attacks, path);
+}
+
+/// Parses (or loads) the data for the configured approach, repeats the sampling and the linkage attacks and writes
+/// the experiment record.
+fn repeat(config: &cli::Config) {
+    let experiment = if config.approach == "sequence" {
+        log::info!("Parsing data for sequence alignment-based approach...");
+        let dataset: Dataset<Sequence> =
+            cache::load_or_parse(config, parse::parse_to_sequence).unwrap();
+        write_user_id_map(config, &dataset.user_ids, dataset.user_to_traces_map.keys());
+        repeat::repeat(config, &dataset.user_to_traces_map, sequence::evaluation::eval).unwrap()
+    } else {
+        log::info!("Parsing data for frequency-based approach...");
+        let dataset: Dataset<Histogram> =
+            cache::load_or_parse(config, parse::parse_to_frequency).unwrap();
+        write_user_id_map(config, &dataset.user_ids, dataset.user_to_traces_map.keys());
+        repeat::repeat(config, &dataset.user_to_traces_map, frequency::evaluation::eval).unwrap()
+    };
+
+    log::info!("{:<16} {:>8} {:>8} {:>8} {:>8}", "score", "mean", "std", "ci_lower", "ci_upper");
+    let aggregates = [
+        ("top_1", &experiment.top_1),
+        ("top_10", &experiment.top_10),
+        ("top_10_percent", &experiment.top_10_percent),
+        ("reciprocal_rank", &experiment.mean_reciprocal_rank),
+    ];
+    for (name, aggregate) in aggregates.iter() {
+        log::info!(
+            "{:<16} {:>8.4} {:>8.4} {:>8.4} {:>8.4}",
+            name,
+            aggregate.mean,
+            aggregate.std,
+            aggregate.ci_lower,
+            aggregate.ci_upper
+        );
+    }
+
+    let path = &config.repeat.as_ref().unwrap().experiment_path;
+    experiment
+        .write_to_path(path)
+        .expect("Error writing experiment record.");
+    log::info!("Wrote experiment record to {:?}.", path);
+}
+
+/// Parses (or loads) the data for the configured approach and reports its quality.
+fn report(config: &cli::Config) {
+    let report = if config.approach == "sequence" {
+        let dataset: Dataset<Sequence> =
+            cache::load_or_parse(config, parse::parse_to_sequence).unwrap();
+        report::Report::new(config, &dataset)
+    } else {
+        let dataset: Dataset<Histogram> =
+            cache::load_or_parse(config, parse::parse_to_frequency).unwrap();
+        report::Report::new(config, &dataset)
+    };
+    report.print_table();
+    report
+        .write_to_path(&config.report_path)
+        .expect("Error writing data quality report.");
+    log::info!("Wrote data quality report to {:?}.", config.report_path);
+}
+
+/// Number of paired attacks along with McNemar's test on them.
+type PairedTest = (usize, stats::McNemar);
+
+/// Runs the evaluation once per missing-value policy, applied to all selected fields, and reports how the top-1
+/// accuracy changes compared to treating missing values as a category of their own. Each run is tagged with its
+/// policy.
+///
+/// The sampling maps of the first policy are reused by the others, so that the attacks are paired and compared with
+/// McNemar's test. Skipping points may change the users or their traces, though, in which case the policy is
+/// sampled anew and not tested.
+fn report_missing_policies(config: &cli::Config) {
+    let mut baseline: Option<Evaluation> = None;
+    let mut results: Vec<(MissingPolicy, f64, Option<PairedTest>)> = Vec::with_capacity(MISSING_POLICIES.len());
+    for policy in MISSING_POLICIES.iter() {
+        log::info!("Evaluating missing-value policy {:?}...", policy);
+        let mut policy_config = config.clone();
+        // Every policy is evaluated as a run of its own
+        policy_config.run_id = utils::gen_run_id();
+        policy_config.missing_policy = Some(*policy);
+        for field in config.fields.iter() {
+            let name = &config.schema.attributes[*field].name;
+            policy_config.schema.set_missing_policy(name, *policy).unwrap();
+        }
+        let evaluation = evaluate(&policy_config, baseline.as_ref().map(|baseline| &baseline.sampling));
+        let test = baseline.as_ref().and_then(|baseline| paired_test(baseline, &evaluation));
+        results.push((*policy, evaluation.scores.top_1, test));
+        if baseline.is_none() {
+            baseline = Some(evaluation);
+        }
+    }
+
+    let baseline = results[0].1;
+    log::info!("{:<12} {:>8} {:>8} {:>8} {:>10}", "policy", "top_1", "change", "paired", "mcnemar_p");
+    for (policy, top_1, test) in results.iter() {
+        let (num_paired, p_value) = match test {
+            Some((num_paired, test)) => (num_paired.to_string(), format!("{:.4}", test.p_value)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        log::info!(
+            "{:<12} {:>8.4} {:>+8.4} {:>8} {:>10}",
+            format!("{:?}", policy),
+            top_1,
+            top_1 - baseline,
+            num_paired,
+            p_value
+        );
+    }
+}
+
+/// Compares the rank-1 outcomes of two evaluations on the same sampling maps with McNemar's exact test. Returns the
+/// number of paired attacks along with the test, or none if the evaluations were not sampled from the same traces.
+fn paired_test(baseline: &Evaluation, candidate: &Evaluation) -> Option<PairedTest> {
+    if baseline.sampling.user_to_num_traces_map != candidate.sampling.user_to_num_traces_map {
+        return None;
+    }
+    let outcomes = top_1_outcomes(baseline);
+    let (baseline, candidate): (Vec<bool>, Vec<bool>) = top_1_outcomes(candidate)
+        .into_iter()
+        .filter_map(|(user, is_top_1)| outcomes.get(user).map(|baseline| (*baseline, is_top_1)))
+        .unzip();
+    if baseline.is_empty() {
+        return None;
+    }
+    Some((baseline.len(), stats::mcnemar(&baseline, &candidate)))
+}
+
+/// Returns whether the attacks on the present target users succeed at rank 1, by original user id.
+fn top_1_outcomes(evaluation: &Evaluation) -> BTreeMap<&str, bool> {
+    evaluation
+        .scores
+        .attacks
+        .iter()
+        .filter(|attack| attack.is_present)
+        .map(|attack| (evaluation.user_ids.original(attack.user), attack.is_top_1))
+        .collect()
+}
+
+/// Parses the raw data for the configured approach and writes it to the binary dataset cache.
+fn prepare(config: &cli::Config) {
+    let key = cache::cache_key(config).unwrap();
+    let path = cache::cache_path(config, &key);
+    if config.approach == "sequence" {
+        log::info!("Parsing data for sequence alignment-based approach...");
+        let dataset: Dataset<Sequence> = parse::parse_to_sequence(config).unwrap();
+        write_user_id_map(config, &dataset.user_ids, dataset.user_to_traces_map.keys());
+        cache::write(&path, &dataset).expect("Error writing dataset cache.");
+    } else {
+        log::info!("Parsing data for frequency-based approach...");
+        let dataset: Dataset<Histogram> = parse::parse_to_frequency(config).unwrap();
+        write_user_id_map(config, &dataset.user_ids, dataset.user_to_traces_map.keys());
+        cache::write(&path, &dataset).expect("Error writing dataset cache.");
+    }
+    log::info!("Wrote dataset cache to {:?}.", path);
+}
+
+/// Writes the original ids of the users that remain after parsing to file, if requested.
+fn write_user_id_map<'a>(
+    config: &cli::Config,
+    user_ids: &UserIds,
+    ids: impl Iterator<Item = &'a u32>,
+) {
+    if let Some(path) = &config.user_id_map {
+        log::info!("Writing user id mapping to {:?}...", path);
+        user_ids
+            .write_to_path(path, ids)
+            .expect("Error writing user id mapping.");
     }
 }
@@ -0,0 +1,134 @@
+use crate::utils::Attack;
+
+use csv::Writer;
+use ordered_float::OrderedFloat;
+use serde::Serialize;
+use std::{cmp::Reverse, error::Error, path::Path};
+
+/// Point of the detection error tradeoff (DET) curve, i.e. the error rates when rejecting all attacks whose best
+/// candidate is worse than the threshold.
+#[derive(Debug, Clone, Serialize)]
+pub struct DetPoint {
+    pub threshold: f64,
+    /// Share of the absent target users that are accepted
+    pub false_match_rate: f64,
+    /// Share of the present target users that are rejected or linked to the wrong user
+    pub false_non_match_rate: f64,
+}
+
+/// Scores of the accept/reject decisions in the open-world mode.
+#[derive(Debug, Clone, Serialize)]
+pub struct OpenWorldScores {
+    pub num_present: usize,
+    pub num_absent: usize,
+    /// Share of the present target users that are linked to the right user and accepted
+    pub true_match_rate: Option<f64>,
+    /// Share of the absent target users that are accepted
+    pub false_match_rate: Option<f64>,
+    /// Share of all attacks that are decided correctly, i.e. present target users that are linked to the right user
+    /// and accepted and absent target users that are rejected
+    pub rejection_accuracy: Option<f64>,
+    /// Error rate at the threshold where false match rate and false non-match rate are closest
+    pub equal_error_rate: f64,
+    pub det: Vec<DetPoint>,
+}
+
+fn rate(count: usize, total: usize) -> f64 {
+    count as f64 / total as f64
+}
+
+impl OpenWorldScores {
+    /// Computes the scores of the attacks. The rates at the rejection threshold are only computed if a threshold is
+    /// given, the DET curve is computed over all thresholds. Rates are NaN if there are no present or no absent
+    /// target users.
+    pub fn new(attacks: &[Attack], rejection_threshold: Option<f64>, higher_is_better: bool) -> OpenWorldScores {
+        // Orient the values so that higher is always better, attacks without any candidate are never accepted
+        let similarity = |value: f64| {
+            if value.is_nan() {
+                f64::NEG_INFINITY
+            } else if higher_is_better {
+                value
+            } else {
+                -value
+            }
+        };
+        let num_present = attacks.iter().filter(|attack| attack.is_present).count();
+        let num_absent = attacks.len() - num_present;
+
+        let (true_match_rate, false_match_rate, rejection_accuracy) = match rejection_threshold {
+            Some(threshold) => {
+                let is_accepted = |attack: &Attack| similarity(attack.best_value) >= similarity(threshold);
+                let num_true_matches = attacks
+                    .iter()
+                    .filter(|attack| attack.is_present && attack.is_top_1 && is_accepted(attack))
+                    .count();
+                let num_false_matches = attacks
+                    .iter()
+                    .filter(|attack| !attack.is_present && is_accepted(attack))
+                    .count();
+                (
+                    Some(rate(num_true_matches, num_present)),
+                    Some(rate(num_false_matches, num_absent)),
+                    Some(rate(
+                        num_true_matches + num_absent - num_false_matches,
+                        attacks.len(),
+                    )),
+                )
+            }
+            None => (None, None, None),
+        };
+
+        // Lower the threshold step by step from accepting no attack to accepting all attacks
+        let mut sorted: Vec<&Attack> = attacks.iter().collect();
+        sorted.sort_unstable_by_key(|attack| Reverse(OrderedFloat(similarity(attack.best_value))));
+        let mut det: Vec<DetPoint> = vec![DetPoint {
+            threshold: similarity(f64::INFINITY),
+            false_match_rate: rate(0, num_absent),
+            false_non_match_rate: rate(num_present, num_present),
+        }];
+        let (mut num_true_matches, mut num_false_matches) = (0, 0);
+        for (idx, attack) in sorted.iter().enumerate() {
+            if attack.is_present && attack.is_top_1 {
+                num_true_matches += 1;
+            } else if !attack.is_present {
+                num_false_matches += 1;
+            }
+            // Attacks with the same value are accepted or rejected together
+            if idx + 1 < sorted.len() && sorted[idx + 1].best_value == attack.best_value {
+                continue;
+            }
+            det.push(DetPoint {
+                threshold: attack.best_value,
+                false_match_rate: rate(num_false_matches, num_absent),
+                false_non_match_rate: 1. - rate(num_true_matches, num_present),
+            });
+        }
+        let equal_error_rate = det
+            .iter()
+            .min_by_key(|point| OrderedFloat((point.false_match_rate - point.false_non_match_rate).abs()))
+            .map_or(f64::NAN, |point| (point.false_match_rate + point.false_non_match_rate) / 2.);
+
+        OpenWorldScores {
+            num_present: num_present,
+            num_absent: num_absent,
+            true_match_rate: true_match_rate,
+            false_match_rate: false_match_rate,
+            rejection_accuracy: rejection_accuracy,
+            equal_error_rate: equal_error_rate,
+            det: det,
+        }
+    }
+
+    /// Writes the DET curve to a CSV file.
+    pub fn write_det_to_path(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut wtr = Writer::from_path(path)?;
+        for point in self.det.iter() {
+            wtr.serialize(point)?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
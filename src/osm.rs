@@ -0,0 +1,444 @@
+use crate::spatial::EARTH_RADIUS;
+
+use osmpbf::{Element, ElementReader};
+use quick_xml::{events::Event, Reader};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, error::Error, str};
+
+/// Maximum distance in meters between a point and the address node its postcode is taken from.
+const POSTCODE_SEARCH_RADIUS: f64 = 500.0;
+
+/// Values of the highway tag that cannot be travelled by vehicles and are therefore never matched.
+const EXCLUDED_HIGHWAYS: &[&str] = &[
+    "footway",
+    "path",
+    "pedestrian",
+    "steps",
+    "cycleway",
+    "bridleway",
+    "corridor",
+    "proposed",
+    "construction",
+];
+
+/// Property of the matched road that a road attribute takes as value.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoadProperty {
+    Street,
+    Highway,
+    Postcode,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Road {
+    pub street: String,
+    pub highway: String,
+    pub postcode: String,
+}
+
+/// Straight line between two consecutive nodes of a road in projected coordinates.
+#[derive(Debug)]
+struct Segment {
+    start: (f64, f64),
+    end: (f64, f64),
+    road_idx: usize,
+}
+
+/// Grid-based spatial index over projected coordinates.
+#[derive(Debug, Default)]
+struct Grid {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+/// Road network extracted from an OpenStreetMap file that raw GPS points are snapped onto.
+///
+/// Points are matched to the closest road segment within the maximum snapping distance. Each point is snapped on its
+/// own, so this is nearest-segment snapping rather than map matching: the route between consecutive points and the
+/// connectivity of the roads are not taken into account. Coordinates are projected onto a plane (equirectangular
+/// projection around the center of the extract), which is accurate enough for the distances involved.
+#[derive(Debug)]
+pub struct RoadGraph {
+    roads: Vec<Road>,
+    segments: Vec<Segment>,
+    segment_grid: Grid,
+    addresses: Vec<((f64, f64), String)>,
+    address_grid: Grid,
+    ref_lat: f64,
+}
+
+/// Raw nodes, ways and address points collected while reading an OpenStreetMap file.
+#[derive(Default)]
+struct OsmData {
+    nodes: HashMap<i64, (f64, f64)>,
+    ways: Vec<(Vec<i64>, Road)>,
+    addresses: Vec<((f64, f64), String)>,
+}
+
+impl Grid {
+    fn new(cell_size: f64) -> Grid {
+        Grid {
+            cell_size: cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    fn cell(&self, point: (f64, f64)) -> (i64, i64) {
+        (
+            (point.0 / self.cell_size).floor() as i64,
+            (point.1 / self.cell_size).floor() as i64,
+        )
+    }
+
+    /// Inserts an item that covers the line between the two points. The line is split into pieces no longer than a
+    /// cell, each of which covers the at most four cells of its bounding box, so that a long diagonal line does not
+    /// fill the whole bounding box of its end points.
+    fn insert(&mut self, start: (f64, f64), end: (f64, f64), item: usize) {
+        let num_pieces = (dist_between(start, end) / self.cell_size).ceil().max(1.) as usize;
+        let point_at = |piece: usize| {
+            let t = piece as f64 / num_pieces as f64;
+            (start.0 + t * (end.0 - start.0), start.1 + t * (end.1 - start.1))
+        };
+        for piece in 0..num_pieces {
+            let (x_0, y_0) = self.cell(point_at(piece));
+            let (x_1, y_1) = self.cell(point_at(piece + 1));
+            for x in x_0.min(x_1)..=x_0.max(x_1) {
+                for y in y_0.min(y_1)..=y_0.max(y_1) {
+                    // Consecutive pieces share cells, which hold the item only once
+                    let items = self.cells.entry((x, y)).or_default();
+                    if items.last() != Some(&item) {
+                        items.push(item);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns all items stored in the cell of the point and its eight neighbouring cells.
+    fn neighbours(&self, point: (f64, f64)) -> impl Iterator<Item = &usize> {
+        let (x, y) = self.cell(point);
+        (x - 1..=x + 1)
+            .flat_map(move |x| (y - 1..=y + 1).map(move |y| (x, y)))
+            .filter_map(move |key| self.cells.get(&key))
+            .flatten()
+    }
+}
+
+impl OsmData {
+    fn add_node<'a>(&mut self, id: i64, lat: f64, lon: f64, tags: impl Iterator<Item = (&'a str, &'a str)>) {
+        self.nodes.insert(id, (lat, lon));
+        for (key, value) in tags {
+            if key == "addr:postcode" {
+                self.addresses.push(((lat, lon), value.to_string()));
+            }
+        }
+    }
+
+    fn add_way<'a>(&mut self, refs: Vec<i64>, tags: impl Iterator<Item = (&'a str, &'a str)>) {
+        let mut road = Road::default();
+        for (key, value) in tags {
+            match key {
+                "name" => road.street = value.to_string(),
+                "highway" => road.highway = value.to_string(),
+                "postal_code" | "addr:postcode" => road.postcode = value.to_string(),
+                _ => (),
+            }
+        }
+        if !road.highway.is_empty() && !EXCLUDED_HIGHWAYS.contains(&road.highway.as_str()) {
+            self.ways.push((refs, road));
+        }
+    }
+
+    /// Reads nodes and ways from an OpenStreetMap PBF file.
+    fn from_pbf(path: &str) -> Result<OsmData, Box<dyn Error>> {
+        let mut data = OsmData::default();
+        let reader = ElementReader::from_path(path)?;
+        reader.for_each(|element| match element {
+            Element::Node(node) => data.add_node(node.id(), node.lat(), node.lon(), node.tags()),
+            Element::DenseNode(node) => data.add_node(node.id(), node.lat(), node.lon(), node.tags()),
+            Element::Way(way) => data.add_way(way.refs().collect(), way.tags()),
+            Element::Relation(_) => (),
+        })?;
+        Ok(data)
+    }
+
+    /// Reads nodes and ways from an OpenStreetMap XML file.
+    fn from_xml(path: &str) -> Result<OsmData, Box<dyn Error>> {
+        let mut data = OsmData::default();
+        let mut reader = Reader::from_file(path)?;
+        let mut buf = Vec::new();
+
+        // Element that is currently read along with its attributes, node references and tags
+        let mut element: Option<(Vec<u8>, HashMap<String, String>)> = None;
+        let mut refs: Vec<i64> = Vec::new();
+        let mut tags: Vec<(String, String)> = Vec::new();
+
+        loop {
+            let event = reader.read_event(&mut buf)?;
+            let is_empty = matches!(event, Event::Empty(_));
+            match event {
+                Event::Start(ref e) | Event::Empty(ref e) => {
+                    let mut attributes = HashMap::new();
+                    for attr in e.attributes() {
+                        let attr = attr?;
+                        attributes.insert(
+                            str::from_utf8(attr.key)?.to_string(),
+                            str::from_utf8(&attr.unescaped_value()?)?.to_string(),
+                        );
+                    }
+                    match e.name() {
+                        b"node" | b"way" => {
+                            element = Some((e.name().to_vec(), attributes));
+                            refs.clear();
+                            tags.clear();
+                        }
+                        b"nd" => {
+                            if let Some(id) = attributes.get("ref") {
+                                refs.push(id.parse()?);
+                            }
+                        }
+                        b"tag" => {
+                            if let (Some(key), Some(value)) = (attributes.remove("k"), attributes.remove("v")) {
+                                tags.push((key, value));
+                            }
+                        }
+                        _ => (),
+                    }
+                    if is_empty && (e.name() == b"node" || e.name() == b"way") {
+                        data.finish_xml_element(element.take(), &mut refs, &tags)?;
+                    }
+                }
+                Event::End(ref e)
+                    if (e.name() == b"node" || e.name() == b"way") => {
+                        data.finish_xml_element(element.take(), &mut refs, &tags)?;
+                    }
+                Event::Eof => break,
+                _ => (),
+            }
+            buf.clear();
+        }
+        Ok(data)
+    }
+
+    fn finish_xml_element(
+        &mut self,
+        element: Option<(Vec<u8>, HashMap<String, String>)>,
+        refs: &mut Vec<i64>,
+        tags: &[(String, String)],
+    ) -> Result<(), Box<dyn Error>> {
+        let tags = tags.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        match element {
+            Some((name, attributes)) if name == b"node" => {
+                let get = |key: &str| -> Result<&String, Box<dyn Error>> {
+                    attributes
+                        .get(key)
+                        .ok_or_else(|| format!("Error: OSM node without attribute {:?}.", key).into())
+                };
+                self.add_node(get("id")?.parse()?, get("lat")?.parse()?, get("lon")?.parse()?, tags);
+            }
+            Some(_) => self.add_way(std::mem::take(refs), tags),
+            None => (),
+        }
+        Ok(())
+    }
+}
+
+impl RoadGraph {
+    /// Loads the road network from an OpenStreetMap extract in PBF (.osm.pbf) or XML (.osm) format.
+    pub fn from_path(path: &str, max_snap_distance: f64) -> Result<RoadGraph, Box<dyn Error>> {
+        // The snap distance is the cell size of the segment grid
+        if max_snap_distance.is_nan() || max_snap_distance <= 0. {
+            return Err(format!(
+                "Error: The maximum snap distance must be positive, got {:?}.",
+                max_snap_distance
+            )
+            .into());
+        }
+        let OsmData {
+            nodes,
+            ways,
+            addresses,
+        } = if path.ends_with(".pbf") {
+            OsmData::from_pbf(path)?
+        } else {
+            OsmData::from_xml(path)?
+        };
+        if nodes.is_empty() {
+            return Err(format!("Error: No nodes found in OSM file {:?}.", path).into());
+        }
+        let ref_lat = nodes.values().map(|(lat, _)| lat).sum::<f64>() / nodes.len() as f64;
+
+        let mut graph = RoadGraph {
+            roads: Vec::with_capacity(ways.len()),
+            segments: Vec::new(),
+            segment_grid: Grid::new(max_snap_distance),
+            addresses: Vec::with_capacity(addresses.len()),
+            address_grid: Grid::new(POSTCODE_SEARCH_RADIUS),
+            ref_lat: ref_lat,
+        };
+
+        for (refs, road) in ways.into_iter() {
+            let road_idx = graph.roads.len();
+            let points: Vec<(f64, f64)> = refs
+                .iter()
+                .filter_map(|id| nodes.get(id))
+                .map(|(lat, lon)| graph.project(*lat, *lon))
+                .collect();
+            for pair in points.windows(2) {
+                graph.segment_grid.insert(pair[0], pair[1], graph.segments.len());
+                graph.segments.push(Segment {
+                    start: pair[0],
+                    end: pair[1],
+                    road_idx: road_idx,
+                });
+            }
+            graph.roads.push(road);
+        }
+
+        for ((lat, lon), postcode) in addresses.into_iter() {
+            let point = graph.project(lat, lon);
+            graph.address_grid.insert(point, point, graph.addresses.len());
+            graph.addresses.push((point, postcode));
+        }
+
+        log::info!(
+            "Loaded {:?} roads with {:?} segments from {:?}",
+            graph.roads.len(),
+            graph.segments.len(),
+            path
+        );
+        Ok(graph)
+    }
+
+    /// Projects the coordinates onto a plane with distances in meters.
+    fn project(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let x = EARTH_RADIUS * lon.to_radians() * self.ref_lat.to_radians().cos();
+        let y = EARTH_RADIUS * lat.to_radians();
+        (x, y)
+    }
+
+    /// Snaps the point onto the closest road within the maximum snapping distance.
+    ///
+    /// Returns the matched road along with its postcode. If the road itself carries no postcode, the postcode of the
+    /// closest address node is used instead.
+    pub fn match_point(&self, lat: f64, lon: f64) -> Option<Road> {
+        let point = self.project(lat, lon);
+        let max_dist = self.segment_grid.cell_size;
+        let (dist, segment_idx) = self
+            .segment_grid
+            .neighbours(point)
+            .map(|idx| (dist_to_segment(point, &self.segments[*idx]), *idx))
+            .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap())?;
+        if dist > max_dist {
+            return None;
+        }
+
+        let mut road = self.roads[self.segments[segment_idx].road_idx].clone();
+        if road.postcode.is_empty() {
+            let closest_address = self
+                .address_grid
+                .neighbours(point)
+                .map(|idx| (dist_between(point, self.addresses[*idx].0), *idx))
+                .filter(|(dist, _)| *dist <= POSTCODE_SEARCH_RADIUS)
+                .min_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+            if let Some((_, idx)) = closest_address {
+                road.postcode = self.addresses[idx].1.clone();
+            }
+        }
+        Some(road)
+    }
+}
+
+impl Road {
+    pub fn get(&self, property: RoadProperty) -> &str {
+        match property {
+            RoadProperty::Street => &self.street,
+            RoadProperty::Highway => &self.highway,
+            RoadProperty::Postcode => &self.postcode,
+        }
+    }
+}
+
+fn dist_between(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// Calculates the distance between a point and the closest point on a segment.
+fn dist_to_segment(point: (f64, f64), segment: &Segment) -> f64 {
+    let (dx, dy) = (segment.end.0 - segment.start.0, segment.end.1 - segment.start.1);
+    let len_sq = dx * dx + dy * dy;
+    if len_sq == 0. {
+        return dist_between(point, segment.start);
+    }
+    let t = (((point.0 - segment.start.0) * dx + (point.1 - segment.start.1) * dy) / len_sq).clamp(0.0, 1.0);
+    dist_between(point, (segment.start.0 + t * dx, segment.start.1 + t * dy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two roads, a footway and an address node around Munich, as XML and as PBF generated from the XML.
+    const FIXTURES: [&str; 2] = [
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/roads.osm"),
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/roads.osm.pbf"),
+    ];
+
+    #[test]
+    fn loads_xml_and_pbf() {
+        for path in FIXTURES.iter() {
+            let graph = RoadGraph::from_path(path, 50.).unwrap();
+            // The footway is excluded
+            let streets: Vec<&str> = graph.roads.iter().map(|road| road.street.as_str()).collect();
+            assert_eq!(streets, ["Hauptstraße", "Ringstraße"], "{}", path);
+            assert_eq!(graph.roads[1].highway, "primary");
+            assert_eq!(graph.roads[1].postcode, "80331");
+            assert_eq!(graph.segments.len(), 2);
+            assert_eq!(graph.addresses.len(), 1);
+            assert_eq!(graph.addresses[0].1, "80333");
+        }
+    }
+
+    #[test]
+    fn snaps_to_nearest_segment() {
+        for path in FIXTURES.iter() {
+            let graph = RoadGraph::from_path(path, 50.).unwrap();
+            // About 30 meters north of the middle of the street
+            let road = graph.match_point(48.10027, 11.51).unwrap();
+            assert_eq!(road.street, "Hauptstraße", "{}", path);
+            assert_eq!(road.highway, "residential");
+            // Half way along the diagonal road, which spans many cells
+            let road = graph.match_point(48.11, 11.51).unwrap();
+            assert_eq!(road.street, "Ringstraße", "{}", path);
+            // About 100 meters away from the street and next to the excluded footway
+            assert!(graph.match_point(48.1009, 11.505).is_none());
+            assert!(graph.match_point(48.0901, 11.5025).is_none());
+        }
+    }
+
+    #[test]
+    fn looks_up_postcodes() {
+        let graph = RoadGraph::from_path(FIXTURES[0], 50.).unwrap();
+        // The road carries its own postcode
+        assert_eq!(graph.match_point(48.11, 11.51).unwrap().postcode, "80331");
+        // The street takes the postcode of the address node within the search radius
+        assert_eq!(graph.match_point(48.1001, 11.51).unwrap().postcode, "80333");
+        // The end of the street is too far from the address node
+        assert_eq!(graph.match_point(48.1001, 11.5001).unwrap().postcode, "");
+    }
+
+    #[test]
+    fn grid_covers_cells_along_segment() {
+        let mut grid = Grid::new(10.);
+        grid.insert((0., 0.), (1000., 1000.), 0);
+        // The diagonal covers the cells along it, not the 101 x 101 cells of its bounding box
+        assert!(grid.cells.len() < 400, "{}", grid.cells.len());
+        assert!(grid.cells.values().all(|items| items == &[0]));
+        for step in 0..=100 {
+            let point = (step as f64 * 10. + 3., step as f64 * 10. - 3.);
+            assert!(grid.neighbours(point).any(|item| *item == 0), "{:?}", point);
+        }
+        assert!(grid.neighbours((1000., 0.)).next().is_none());
+    }
+}
@@ -1,373 +1,491 @@
+use crate::binning;
 use crate::cli::Config;
-use crate::frequency::{maths, trace::FreqTrace};
-use crate::sequence::trace::SeqTrace;
-
-use chrono::{prelude::DateTime, Datelike, Timelike, Utc};
+use crate::frequency::trace::Histogram;
+use crate::osm::RoadGraph;
+use crate::reader::{self, RowReader};
+use crate::schema::{self, Attribute, AttributeKind, MissingPolicy, Schema};
+use crate::segment::Segmenter;
+use crate::sequence::trace::Sequence;
+use crate::sort;
+use crate::spatial;
+use crate::trace::{Column, Trace};
+use crate::users::UserIds;
+
+use chrono_tz::Tz;
+use csv::StringRecord;
 use indexmap::IndexSet;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::BTreeMap,
     convert::TryFrom,
     error::Error,
-    fmt::Display,
-    str::FromStr,
-    string::ParseError,
-    time::{Duration, UNIX_EPOCH},
 };
 
-#[derive(Debug, Deserialize)]
+/// A single observation of a user holding one value per attribute of the schema.
+#[derive(Debug)]
 pub struct Record {
     pub user_id: String,
     pub timestamp: f64,
-    pub heading: String,
-    pub speed: String,
-    pub street: String,
-    pub postcode: String,
-    pub state: String,
-    pub highway: String,
-    pub hamlet: String,
-    pub suburb: String,
-    pub village: String,
-    pub location_code: String,
+    /// Raw coordinates, only read if required by a spatial attribute or the segmentation
+    pub coordinates: Option<(f64, f64)>,
+    pub values: Vec<String>,
 }
 
-#[derive(PartialEq, Debug)]
-pub enum DataFields {
-    Speed,
-    Day,
-    Hour,
-    Heading,
-    Street,
-    Postcode,
-    State,
-    Highway,
-    Hamlet,
-    Suburb,
-    Village,
-    LocationCode,
+/// The segmented traces of all users along with the mappings needed to translate them back to the raw data.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Dataset<C> {
+    pub user_to_traces_map: BTreeMap<u32, Vec<Trace<C>>>,
+    pub user_ids: UserIds,
+    /// One dictionary per attribute that maps the integer values back to the raw values
+    pub dictionaries: Vec<IndexSet<String>>,
+    pub stats: ParseStats,
 }
 
-impl Display for DataFields {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self)
-    }
+/// Counters collected while parsing the raw data.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ParseStats {
+    /// Number of points read from the input
+    pub num_points: usize,
+    /// Number of points dropped due to missing values
+    pub num_skipped_points: usize,
+    /// Number of points with an earlier timestamp than the preceding point of the same user in the input
+    pub num_monotonicity_violations: usize,
+    /// Number of empty values per attribute
+    pub num_empty_values: Vec<usize>,
+    /// Number of traces dropped for having less than the minimum number of points
+    pub num_short_traces: usize,
+    /// Number of traces dropped for exceeding the maximum duration
+    pub num_long_traces: usize,
+    /// Number of users dropped for having less than the minimum number of traces
+    pub num_dropped_users: usize,
+    /// Number of traces of the dropped users
+    pub num_dropped_user_traces: usize,
 }
 
-impl FromStr for DataFields {
-    type Err = ParseError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_lowercase().as_ref() {
-            "speed" => Ok(Self::Speed),
-            "day" => Ok(Self::Day),
-            "hour" => Ok(Self::Hour),
-            "heading" => Ok(Self::Heading),
-            "street" => Ok(Self::Street),
-            "postcode" => Ok(Self::Postcode),
-            "state" => Ok(Self::State),
-            "highway" => Ok(Self::Highway),
-            "hamlet" => Ok(Self::Hamlet),
-            "suburb" => Ok(Self::Suburb),
-            "village" => Ok(Self::Village),
-            "location_code" => Ok(Self::LocationCode),
-            x => panic!("Error: Wrong data field supplied: {:?}", x),
-        }
-    }
+/// Origin of the value of an attribute within a row.
+enum Source {
+    Column(usize),
+    Coordinates,
+    /// The attribute is not selected for the analysis and therefore not materialized
+    Skipped,
 }
 
-/// Parses the raw data into a convenient tree map for the histogram-based approach.
-pub fn parse_to_frequency(
-    config: &Config,
-) -> Result<BTreeMap<u32, Vec<FreqTrace>>, Box<dyn Error>> {
-    let mut prev_time: f64 = 0.0;
-    let mut prev_client = String::new();
-    let mut trace_len: usize = 0;
-    let mut user_id: u32 = 0;
-
-    let mut user_to_freq_map: BTreeMap<u32, Vec<FreqTrace>> = BTreeMap::new();
-    let mut reader = csv::Reader::from_path(&config.path)?;
-
-    for result in reader.deserialize() {
-        let record: Record = result?;
+/// Positions of the columns referenced by the schema in the header of the input file.
+struct ColumnIndices {
+    user_id: usize,
+    timestamp: usize,
+    coordinates: Option<(usize, usize)>,
+    /// Column holding the time zone of each row
+    timezone: Option<usize>,
+    /// Time zone of rows without a time zone of their own
+    default_timezone: Tz,
+    attributes: Vec<Source>,
+}
 
-        if prev_client != record.user_id && !prev_client.is_empty() {
-            // Check last mobility trace added to previous client
-            let prev_traces_list = user_to_freq_map.get_mut(&user_id).unwrap();
-            if !prev_traces_list.is_empty() {
-                if trace_len < config.min_trace_len {
-                    prev_traces_list.pop();
-                }
+impl ColumnIndices {
+    fn new(
+        schema: &Schema,
+        fields: &[usize],
+        needs_coordinates: bool,
+        headers: &StringRecord,
+    ) -> Result<ColumnIndices, Box<dyn Error>> {
+        let find = |column: &str| -> Result<usize, Box<dyn Error>> {
+            headers
+                .iter()
+                .position(|header| header == column)
+                .ok_or_else(|| format!("Error: Column {:?} not found in input file.", column).into())
+        };
+        let mut attributes = Vec::with_capacity(schema.attributes.len());
+        for (idx, attr) in schema.attributes.iter().enumerate() {
+            if !fields.contains(&idx) {
+                attributes.push(Source::Skipped);
+            } else if attr.is_spatial() {
+                attributes.push(Source::Coordinates);
+            } else {
+                attributes.push(Source::Column(find(attr.column())?));
             }
-            user_id += 1;
         }
+        let coordinates = match &schema.coordinates {
+            Some(coordinates)
+                if needs_coordinates
+                    || attributes.iter().any(|src| matches!(src, Source::Coordinates)) =>
+            {
+                Some((find(&coordinates.lat)?, find(&coordinates.lon)?))
+            }
+            _ => None,
+        };
+        let timezone = match &schema.timezone_column {
+            Some(column) if has_timestamp_attributes(schema, fields) => Some(find(column)?),
+            _ => None,
+        };
+        Ok(ColumnIndices {
+            user_id: find(&schema.user_id)?,
+            timestamp: find(&schema.timestamp)?,
+            coordinates: coordinates,
+            timezone: timezone,
+            default_timezone: schema.timezone(),
+            attributes: attributes,
+        })
+    }
+}
 
-        if !user_to_freq_map.contains_key(&user_id) {
-            user_to_freq_map.insert(user_id, Vec::with_capacity(10));
+/// Returns the names of all columns that need to be read for the given fields.
+fn projected_columns(schema: &Schema, fields: &[usize], needs_coordinates: bool) -> Vec<String> {
+    let mut columns = vec![schema.user_id.clone(), schema.timestamp.clone()];
+    let needs_coordinates =
+        needs_coordinates || fields.iter().any(|idx| schema.attributes[*idx].is_spatial());
+    for idx in fields.iter() {
+        let attr = &schema.attributes[*idx];
+        if !attr.is_spatial() {
+            columns.push(attr.column().to_string());
         }
+    }
+    if let (true, Some(coordinates)) = (needs_coordinates, &schema.coordinates) {
+        columns.push(coordinates.lat.clone());
+        columns.push(coordinates.lon.clone());
+    }
+    if let (true, Some(column)) = (has_timestamp_attributes(schema, fields), &schema.timezone_column) {
+        columns.push(column.clone());
+    }
+    columns
+}
 
-        let traces_list = user_to_freq_map.get_mut(&user_id).unwrap();
+/// Returns true if any of the given fields is derived from a timestamp.
+fn has_timestamp_attributes(schema: &Schema, fields: &[usize]) -> bool {
+    fields
+        .iter()
+        .any(|idx| matches!(schema.attributes[*idx].kind, AttributeKind::Timestamp { .. }))
+}
 
-        if traces_list.is_empty()
-            || trace_len >= config.max_trace_len
-            || record.timestamp - prev_time >= config.delay_limit
-        {
-            if !traces_list.is_empty() {
-                if trace_len < config.min_trace_len
-                    || traces_list.last().unwrap().end_time
-                        - traces_list.last().unwrap().start_time
-                        > config.max_trace_duration
-                {
-                    traces_list.pop();
-                }
-            }
+/// Returns the attributes that need to be read, which are the selected fields and the attributes required by the
+/// segmentation.
+fn required_fields(config: &Config, segmenter: &Segmenter) -> Vec<usize> {
+    let mut fields = config.fields.clone();
+    if let Some(idx) = segmenter.speed_field() {
+        if !fields.contains(&idx) {
+            fields.push(idx);
+        }
+    }
+    fields
+}
 
-            let trace = FreqTrace {
-                speed: HashMap::new(),
-                heading: HashMap::new(),
-                street: HashMap::new(),
-                postcode: HashMap::new(),
-                highway: HashMap::new(),
-                hamlet: HashMap::new(),
-                suburb: HashMap::new(),
-                village: HashMap::new(),
-                state: HashMap::new(),
-                location_code: HashMap::new(),
-                hour: maths::zeros_u32(24),
-                day: maths::zeros_u32(7),
-                start_time: record.timestamp,
-                end_time: record.timestamp,
+impl Record {
+    /// Extracts the values of all schema attributes from a raw row of the input file.
+    ///
+    /// Spatial attributes are derived from the coordinates of the row, either by snapping them onto the road network
+    /// or by assigning them to a cell of a spatial grid.
+    fn from_row(
+        schema: &Schema,
+        indices: &ColumnIndices,
+        road_graph: Option<&RoadGraph>,
+        row: &StringRecord,
+    ) -> Result<Record, Box<dyn Error>> {
+        let timestamp = row[indices.timestamp].trim().parse::<f64>()?;
+        let coordinates = match indices.coordinates {
+            Some((lat_idx, lon_idx)) => Some((
+                row[lat_idx].trim().parse::<f64>()?,
+                row[lon_idx].trim().parse::<f64>()?,
+            )),
+            None => None,
+        };
+        let timezone = match indices.timezone {
+            Some(idx) if !row[idx].trim().is_empty() => schema::parse_timezone(&row[idx])?,
+            _ => indices.default_timezone,
+        };
+        let road = match (road_graph, coordinates) {
+            (Some(graph), Some((lat, lon))) => graph.match_point(lat, lon),
+            _ => None,
+        };
+
+        let mut values = Vec::with_capacity(schema.attributes.len());
+        for (attr, source) in schema.attributes.iter().zip(indices.attributes.iter()) {
+            let raw = match source {
+                Source::Column(idx) => &row[*idx],
+                Source::Coordinates => "",
+                Source::Skipped => {
+                    values.push(String::new());
+                    continue;
+                }
             };
-            traces_list.push(trace);
-            trace_len = 0;
+            let value = match &attr.kind {
+                AttributeKind::Categorical => raw.to_string(),
+                AttributeKind::Numeric => {
+                    if !raw.trim().is_empty() {
+                        raw.trim().parse::<f64>().map_err(|_| {
+                            format!("Error: Non-numeric value {:?} for attribute {:?}.", raw, attr.name)
+                        })?;
+                    }
+                    raw.to_string()
+                }
+                AttributeKind::Timestamp { unit } => {
+                    unit.extract(raw.trim().parse::<f64>()?, timezone).to_string()
+                }
+                AttributeKind::Road { property } => road
+                    .as_ref()
+                    .map(|road| road.get(*property).to_string())
+                    .unwrap_or_default(),
+                AttributeKind::Geohash { precision } => {
+                    let (lat, lon) = coordinates.unwrap();
+                    spatial::geohash(lat, lon, *precision)
+                }
+                AttributeKind::HexGrid { size } => {
+                    let (lat, lon) = coordinates.unwrap();
+                    spatial::hex_cell(lat, lon, *size)
+                }
+            };
+            values.push(value);
         }
+        Ok(Record {
+            user_id: row[indices.user_id].to_string(),
+            timestamp: timestamp,
+            coordinates: coordinates,
+            values: values,
+        })
+    }
+}
 
-        let current_trace = traces_list.last_mut().unwrap();
+/// Returns true if the distance of each trace is required, i.e. if the users are stratified by distance.
+pub fn needs_distance(config: &Config) -> bool {
+    config.stratify == "distance"
+}
 
-        // Extract day and hour from unix timestamp
-        let date = UNIX_EPOCH + Duration::from_secs_f64(record.timestamp.clone());
-        let datetime = DateTime::<Utc>::from(date);
-
-        // Convert from u32 to usize
-        let hour_index: usize = usize::try_from(datetime.hour()).unwrap();
-        let day_index: usize = usize::try_from(datetime.weekday().num_days_from_monday()).unwrap();
-
-        current_trace.hour[hour_index] += 1;
-        current_trace.day[day_index] += 1;
-        current_trace.end_time = record.timestamp;
-
-        *current_trace
-            .speed
-            .entry(record.speed.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .postcode
-            .entry(record.postcode.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .heading
-            .entry(record.heading.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .street
-            .entry(record.street.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .state
-            .entry(record.state.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .highway
-            .entry(record.highway.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .hamlet
-            .entry(record.hamlet.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .suburb
-            .entry(record.suburb.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .village
-            .entry(record.village.clone())
-            .or_insert(0) += 1;
-        *current_trace
-            .location_code
-            .entry(record.location_code.clone())
-            .or_insert(0) += 1;
+/// Opens the input file and returns the reader along with the positions of the schema columns.
+fn open_reader(
+    config: &Config,
+    segmenter: &Segmenter,
+) -> Result<(Box<dyn RowReader>, ColumnIndices), Box<dyn Error>> {
+    if needs_distance(config) && config.schema.coordinates.is_none() {
+        return Err("Error: The distance of traces requires the coordinates of the schema.".into());
+    }
+    let fields = required_fields(config, segmenter);
+    let needs_coordinates = segmenter.needs_coordinates() || needs_distance(config);
+    let columns = projected_columns(&config.schema, &fields, needs_coordinates);
+    let reader = reader::open(&config.path, &columns)?;
+    let indices = ColumnIndices::new(&config.schema, &fields, needs_coordinates, reader.headers())?;
+    Ok((reader, indices))
+}
 
-        prev_time = record.timestamp;
-        prev_client = record.user_id;
-        trace_len += 1;
+/// Loads the road network for map matching if any road attribute is selected.
+fn load_road_graph(config: &Config) -> Result<Option<RoadGraph>, Box<dyn Error>> {
+    let has_road_attributes = config.fields.iter().any(|idx| {
+        matches!(config.schema.attributes[*idx].kind, AttributeKind::Road { .. })
+    });
+    if !has_road_attributes {
+        return Ok(None);
+    }
+    match &config.osm {
+        Some(path) => {
+            log::info!("Loading road network for map matching...");
+            Ok(Some(RoadGraph::from_path(path, config.max_snap_distance)?))
+        }
+        None => Err("Error: Road attributes require an OSM extract supplied via --osm.".into()),
     }
+}
 
-    // Remove any client with less than the minimum number of click traces
-    log::info!(
-        "Numer of users before filtering: {:?}",
-        user_to_freq_map.keys().len()
-    );
-    user_to_freq_map.retain(|_, value| value.len() >= config.min_num_traces);
-    log::info!(
-        "Number of users after filtering: {:?}",
-        user_to_freq_map.keys().len()
-    );
+/// Creates the dictionary that maps the raw values of an attribute to integers.
+///
+/// The dictionary of a timestamp attribute is initialized with all possible values so that each value maps to itself.
+/// Any other dictionary is initialized with the empty value so that missing values map to `MISSING`.
+fn gen_dictionary(attr: &Attribute) -> IndexSet<String> {
+    match &attr.kind {
+        AttributeKind::Timestamp { unit } => (0..unit.num_bins).map(|x| x.to_string()).collect(),
+        _ => std::iter::once(String::new()).collect(),
+    }
+}
 
-    let total_num_traces: usize =
-        user_to_freq_map.iter().map(|(_, val)| val.len()).sum();
-    log::info!(
-        "Total number of mobility traces: {:?}",
-        total_num_traces
-    );
-    Ok(user_to_freq_map)
+/// Parses the raw data into a dataset for the histogram-based approach.
+pub fn parse_to_frequency(config: &Config) -> Result<Dataset<Histogram>, Box<dyn Error>> {
+    parse(config)
 }
 
-/// Parses the raw data into a convenient tree map for the sequence aligment-based approach.
-pub fn parse_to_sequence(
-    config: &Config,
-) -> Result<BTreeMap<u32, Vec<SeqTrace>>, Box<dyn Error>> {
+/// Parses the raw data into a dataset for the sequence aligment-based approach.
+pub fn parse_to_sequence(config: &Config) -> Result<Dataset<Sequence>, Box<dyn Error>> {
+    parse(config)
+}
+
+/// Parses the raw data into a tree map from user to the list of mobility traces of the user.
+///
+/// Users are identified by sequential ids. The mapping to the original user ids and the dictionaries of the attribute
+/// values are returned alongside the tree map.
+/// The values of each attribute are mapped to integers and stored in the column type of the respective approach.
+/// Traces are split according to the configured segmentation strategy and at the maximum trace length. Numeric
+/// attributes with binning are binned once all traces are known.
+/// By default, the records are expected to be grouped by user and sorted by timestamp. Otherwise, the records are
+/// sorted first, which spills to disk if they exceed the sort buffer.
+fn parse<C: Column>(config: &Config) -> Result<Dataset<C>, Box<dyn Error>> {
     let mut prev_time: f64 = 0.0;
+    let mut prev_coordinates: Option<(f64, f64)> = None;
     let mut prev_client = String::new();
     let mut trace_len: usize = 0;
     let mut user_id: u32 = 0;
-
-    let mut user_to_seq_map: BTreeMap<u32, Vec<SeqTrace>> = BTreeMap::new();
-    let mut reader = csv::Reader::from_path(&config.path)?;
-
-    let mut street_set: IndexSet<String> = IndexSet::new();
-    let mut postcode_set: IndexSet<String> = IndexSet::new();
-    let mut state_set: IndexSet<String> = IndexSet::new();
-    let mut speed_set: IndexSet<String> = IndexSet::new();
-    let mut heading_set: IndexSet<String> = IndexSet::new();
-    let mut highway_set: IndexSet<String> = IndexSet::new();
-    let mut hamlet_set: IndexSet<String> = IndexSet::new();
-    let mut suburb_set: IndexSet<String> = IndexSet::new();
-    let mut village_set: IndexSet<String> = IndexSet::new();
-    let mut location_code_set: IndexSet<String> = IndexSet::new();
-
-    for result in reader.deserialize() {
-        let record: Record = result?;
-
-        if prev_client != record.user_id && !prev_client.is_empty() {
-            let prev_traces_list = user_to_seq_map.get_mut(&user_id).unwrap();
-            if !prev_traces_list.is_empty() {
-                if trace_len < config.min_trace_len {
-                    prev_traces_list.pop();
+    let mut user_ids = UserIds::default();
+
+    let mut user_to_traces_map: BTreeMap<u32, Vec<Trace<C>>> = BTreeMap::new();
+    let mut segmenter = Segmenter::new(config)?;
+    let (mut reader, indices) = open_reader(config, &segmenter)?;
+    let road_graph = load_road_graph(config)?;
+
+    // One dictionary per attribute to map each value to an integer
+    let mut dictionaries: Vec<IndexSet<String>> =
+        config.schema.attributes.iter().map(gen_dictionary).collect();
+    let mut values: Vec<u32> = Vec::with_capacity(dictionaries.len());
+
+    // Statistics of the points in the order in which they appear in the input
+    let mut num_points: usize = 0;
+    let mut num_monotonicity_violations: usize = 0;
+    let mut num_empty_values: Vec<usize> = vec![0; dictionaries.len()];
+    let mut prev_point: Option<(String, f64)> = None;
+
+    let mut row = StringRecord::new();
+    let records = std::iter::from_fn(|| match reader.read_row(&mut row) {
+        Ok(true) => {
+            let record = Record::from_row(&config.schema, &indices, road_graph.as_ref(), &row);
+            if let Ok(record) = &record {
+                num_points += 1;
+                if let Some((prev_user_id, prev_timestamp)) = &prev_point {
+                    if *prev_user_id == record.user_id && record.timestamp < *prev_timestamp {
+                        num_monotonicity_violations += 1;
+                    }
+                }
+                for (count, value) in num_empty_values.iter_mut().zip(record.values.iter()) {
+                    if value.is_empty() {
+                        *count += 1;
+                    }
                 }
+                prev_point = Some((record.user_id.clone(), record.timestamp));
             }
-            user_id += 1;
+            Some(record)
+        }
+        Ok(false) => None,
+        Err(err) => Some(Err(err)),
+    });
+    let records: Box<dyn Iterator<Item = Result<Record, Box<dyn Error>>>> = if config.sort {
+        log::info!("Sorting records by user and timestamp...");
+        sort::sort_records(records, config.sort_buffer_size, &std::env::temp_dir())?
+    } else {
+        Box::new(records)
+    };
+
+    let mut warned_unsorted = false;
+
+    // Fields whose missing values cause the point to be dropped
+    let skip_fields: Vec<usize> = config
+        .fields
+        .iter()
+        .filter(|idx| config.schema.attributes[**idx].missing_policy() == MissingPolicy::Skip)
+        .cloned()
+        .collect();
+    let mut num_skipped: usize = 0;
+    let mut num_short_traces: usize = 0;
+    let mut num_long_traces: usize = 0;
+
+    for record in records {
+        let record = record?;
+        if skip_fields.iter().any(|idx| record.values[*idx].is_empty()) {
+            num_skipped += 1;
+            continue;
         }
 
-        if !user_to_seq_map.contains_key(&user_id) {
-            user_to_seq_map.insert(user_id, Vec::with_capacity(10));
+        if prev_client != record.user_id {
+            if !prev_client.is_empty() {
+                // Check last mobility trace added to previous client
+                let prev_traces_list = user_to_traces_map.get_mut(&user_id).unwrap();
+                if !prev_traces_list.is_empty()
+                    && trace_len < config.min_trace_len {
+                        prev_traces_list.pop();
+                        num_short_traces += 1;
+                    }
+            }
+            if !config.sort && !warned_unsorted && user_ids.id(&record.user_id).is_some() {
+                log::warn!("Input is not grouped by user. Consider using --sort true.");
+                warned_unsorted = true;
+            }
+            user_id = user_ids.push(&record.user_id);
+            segmenter.reset();
+        } else if !config.sort && !warned_unsorted && record.timestamp < prev_time {
+            log::warn!("Input is not sorted by timestamp. Consider using --sort true.");
+            warned_unsorted = true;
         }
 
-        let traces_list = user_to_seq_map.get_mut(&user_id).unwrap();
+        user_to_traces_map.entry(user_id).or_insert_with(|| Vec::with_capacity(10));
 
-        if traces_list.is_empty()
-            || trace_len >= config.max_trace_len
-            || record.timestamp - prev_time >= config.delay_limit
-        {
+        let traces_list = user_to_traces_map.get_mut(&user_id).unwrap();
+
+        let is_trace_start = segmenter.is_trace_start(&record);
+        if traces_list.is_empty() || trace_len >= config.max_trace_len || is_trace_start {
             if !traces_list.is_empty() {
-                if trace_len < config.min_trace_len
-                    || traces_list.last().unwrap().end_time
-                        - traces_list.last().unwrap().start_time
-                        > config.max_trace_duration
+                if trace_len < config.min_trace_len {
+                    traces_list.pop();
+                    num_short_traces += 1;
+                } else if traces_list.last().unwrap().end_time
+                    - traces_list.last().unwrap().start_time
+                    > config.max_trace_duration
                 {
                     traces_list.pop();
+                    num_long_traces += 1;
                 }
             }
 
-            let trace = SeqTrace {
-                street: Vec::with_capacity(10),
-                hour: Vec::with_capacity(10),
-                day: 0,
-                start_time: record.timestamp,
-                end_time: record.timestamp,
-                speed: Vec::with_capacity(10),
-                heading: Vec::with_capacity(10),
-                postcode: Vec::with_capacity(10),
-                state: Vec::with_capacity(10),
-                highway: Vec::with_capacity(10),
-                hamlet: Vec::with_capacity(10),
-                suburb: Vec::with_capacity(10),
-                village: Vec::with_capacity(10),
-                location_code: Vec::with_capacity(10),
-            };
-            traces_list.push(trace);
+            traces_list.push(Trace::new(&config.schema, record.timestamp));
             trace_len = 0;
+            prev_coordinates = None;
         }
 
+        values.clear();
+        for (dictionary, value) in dictionaries.iter_mut().zip(record.values) {
+            let (idx, _) = dictionary.insert_full(value);
+            values.push(u32::try_from(idx).unwrap());
+        }
         let current_trace = traces_list.last_mut().unwrap();
-
-        // Extract day and hour from unix timestamp
-        let date = UNIX_EPOCH + Duration::from_secs_f64(record.timestamp.clone());
-        let datetime = DateTime::<Utc>::from(date);
-
-        street_set.insert(record.street.clone());
-        postcode_set.insert(record.postcode.clone());
-        state_set.insert(record.state.clone());
-        heading_set.insert(record.heading.clone());
-        speed_set.insert(record.speed.clone());
-        highway_set.insert(record.highway.clone());
-        hamlet_set.insert(record.hamlet.clone());
-        suburb_set.insert(record.suburb.clone());
-        village_set.insert(record.village.clone());
-        location_code_set.insert(record.location_code.clone());
-
-        current_trace.hour.push(datetime.hour());
-        current_trace.day = datetime.weekday().num_days_from_monday();
-        current_trace.end_time = record.timestamp;
-
-        current_trace
-            .street
-            .push(u32::try_from(street_set.get_full(&record.street).unwrap().0).unwrap());
-        current_trace
-            .postcode
-            .push(u32::try_from(postcode_set.get_full(&record.postcode).unwrap().0).unwrap());
-        current_trace
-            .state
-            .push(u32::try_from(state_set.get_full(&record.state).unwrap().0).unwrap());
-        current_trace
-            .heading
-            .push(u32::try_from(heading_set.get_full(&record.heading).unwrap().0).unwrap());
-        current_trace
-            .speed
-            .push(u32::try_from(speed_set.get_full(&record.speed).unwrap().0).unwrap());
-        current_trace
-            .highway
-            .push(u32::try_from(highway_set.get_full(&record.highway).unwrap().0).unwrap());
-        current_trace
-            .hamlet
-            .push(u32::try_from(hamlet_set.get_full(&record.hamlet).unwrap().0).unwrap());
-        current_trace
-            .suburb
-            .push(u32::try_from(suburb_set.get_full(&record.suburb).unwrap().0).unwrap());
-        current_trace
-            .village
-            .push(u32::try_from(village_set.get_full(&record.village).unwrap().0).unwrap());
-        current_trace.location_code.push(
-            u32::try_from(location_code_set.get_full(&record.location_code).unwrap().0).unwrap(),
-        );
+        current_trace.push(&config.schema, record.timestamp, &values);
+        if let (Some(prev), Some(coordinates)) = (prev_coordinates, record.coordinates) {
+            current_trace.distance += spatial::haversine_dist(prev, coordinates);
+        }
+        prev_coordinates = record.coordinates;
 
         prev_time = record.timestamp;
         prev_client = record.user_id;
         trace_len += 1;
     }
 
+    if !skip_fields.is_empty() {
+        log::info!("Number of points skipped due to missing values: {:?}", num_skipped);
+    }
+
     // Remove any client with less than the minimum number of click traces
     log::info!(
         "Number of users before filtering: {:?}",
-        user_to_seq_map.keys().len()
+        user_to_traces_map.keys().len()
     );
-    user_to_seq_map.retain(|_, value| value.len() >= config.min_num_traces);
+    let num_users = user_to_traces_map.len();
+    let num_traces: usize = user_to_traces_map.values().map(|traces| traces.len()).sum();
+    user_to_traces_map.retain(|_, value| value.len() >= config.min_num_traces);
     log::info!(
         "Number of users after filtering: {:?}",
-        user_to_seq_map.keys().len()
+        user_to_traces_map.keys().len()
     );
 
-    let total_num_traces: usize = user_to_seq_map.iter().map(|(_, val)| val.len()).sum();
+    let total_num_traces: usize = user_to_traces_map.values().map(|val| val.len()).sum();
     log::info!(
-        "Total number of traces: {:?}",
+        "Total number of mobility traces: {:?}",
         total_num_traces
     );
-    Ok(user_to_seq_map)
+    let stats = ParseStats {
+        num_points: num_points,
+        num_skipped_points: num_skipped,
+        num_monotonicity_violations: num_monotonicity_violations,
+        num_empty_values: num_empty_values,
+        num_short_traces: num_short_traces,
+        num_long_traces: num_long_traces,
+        num_dropped_users: num_users - user_to_traces_map.len(),
+        num_dropped_user_traces: num_traces - total_num_traces,
+    };
+    let mut dataset = Dataset {
+        user_to_traces_map: user_to_traces_map,
+        user_ids: user_ids,
+        dictionaries: dictionaries,
+        stats: stats,
+    };
+    binning::apply(config, &mut dataset)?;
+    Ok(dataset)
 }
@@ -0,0 +1,119 @@
+use crate::users::UserIds;
+use crate::utils::Attack;
+
+use csv::{ReaderBuilder, WriterBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+
+/// Candidate user of an attack with its distance (frequency-based) or alignment score (sequence alignment-based).
+#[derive(Serialize)]
+pub struct Candidate<'a> {
+    pub user_id: &'a str,
+    pub value: f64,
+}
+
+/// Ranking of a single attack. The candidates are a list in JSON Lines and a `user_id:value` string separated by
+/// semicolons in CSV.
+#[derive(Serialize)]
+struct Ranking<'a, T> {
+    user_id: &'a str,
+    is_present: bool,
+    rank: Option<usize>,
+    is_top_1: bool,
+    is_top_10: bool,
+    is_top_10_percent: bool,
+    best_value: f64,
+    runner_up_value: Option<f64>,
+    target_value: Option<f64>,
+    candidates: T,
+}
+
+/// Outcome of an attack read back from a rankings file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Outcome {
+    pub user_id: String,
+    pub is_present: bool,
+    pub rank: Option<usize>,
+    pub is_top_1: bool,
+    pub is_top_10: bool,
+    pub is_top_10_percent: bool,
+}
+
+/// Returns the best ranked candidate users of an attack with their original ids.
+pub fn candidates<'a>(user_ids: &'a UserIds, attack: &Attack) -> Vec<Candidate<'a>> {
+    attack
+        .candidates
+        .iter()
+        .map(|(user, value)| Candidate {
+            user_id: user_ids.original(*user),
+            value: *value,
+        })
+        .collect()
+}
+
+fn ranking<'a, T>(user_ids: &'a UserIds, attack: &Attack, candidates: T) -> Ranking<'a, T> {
+    Ranking {
+        user_id: user_ids.original(attack.user),
+        is_present: attack.is_present,
+        rank: attack.rank,
+        is_top_1: attack.is_top_1,
+        is_top_10: attack.is_top_10,
+        is_top_10_percent: attack.is_top_10_percent,
+        best_value: attack.best_value,
+        runner_up_value: attack.runner_up_value,
+        target_value: attack.target_value,
+        candidates: candidates,
+    }
+}
+
+/// Writes the ranking of every attack with the original user ids, one attack per line. Files ending in `.jsonl` are
+/// written as JSON Lines, all other files as CSV.
+pub fn write_to_path(path: &str, user_ids: &UserIds, attacks: &[Attack]) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if path.ends_with(".jsonl") {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for attack in attacks.iter() {
+            serde_json::to_writer(&mut writer, &ranking(user_ids, attack, candidates(user_ids, attack)))?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+    } else {
+        let mut wtr = WriterBuilder::new().has_headers(true).from_path(path)?;
+        for attack in attacks.iter() {
+            let candidates = candidates(user_ids, attack)
+                .iter()
+                .map(|candidate| format!("{}:{}", candidate.user_id, candidate.value))
+                .collect::<Vec<String>>()
+                .join(";");
+            wtr.serialize(ranking(user_ids, attack, candidates))?;
+        }
+        wtr.flush()?;
+    }
+    Ok(())
+}
+
+/// Reads the outcomes of the attacks from a rankings file written by `write_to_path`.
+pub fn read_from_path(path: &str) -> Result<Vec<Outcome>, Box<dyn Error>> {
+    let mut outcomes: Vec<Outcome> = Vec::new();
+    if path.ends_with(".jsonl") {
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                outcomes.push(serde_json::from_str(&line)?);
+            }
+        }
+    } else {
+        let mut rdr = ReaderBuilder::new().has_headers(true).from_path(path)?;
+        for result in rdr.deserialize() {
+            outcomes.push(result?);
+        }
+    }
+    Ok(outcomes)
+}
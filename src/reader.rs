@@ -0,0 +1,328 @@
+use csv::StringRecord;
+use flate2::read::MultiGzDecoder;
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufRead, BufReader, Read},
+};
+use xz2::read::XzDecoder;
+
+#[cfg(feature = "columnar")]
+use arrow::{array::RecordBatch, error::ArrowError, util::display::array_value_to_string};
+
+/// Reads the rows of a dataset as string records.
+///
+/// Readers for columnar formats only materialize the projected columns. The header lists the columns in the
+/// order in which they appear in the rows.
+pub trait RowReader {
+    fn headers(&self) -> &StringRecord;
+
+    /// Reads the next row into the given record. Returns false if there are no rows left.
+    fn read_row(&mut self, row: &mut StringRecord) -> Result<bool, Box<dyn Error>>;
+}
+
+/// File formats supported as input.
+#[derive(Debug, PartialEq)]
+pub enum Format {
+    Csv,
+    Parquet,
+    ArrowIpc,
+}
+
+/// Compression codecs that are transparently decompressed while reading.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    /// Detects the compression from the file extension.
+    fn from_extension(path: &str) -> Compression {
+        let path = path.to_lowercase();
+        if path.ends_with(".gz") {
+            Compression::Gzip
+        } else if path.ends_with(".zst") || path.ends_with(".zstd") {
+            Compression::Zstd
+        } else if path.ends_with(".xz") {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Detects the compression from the magic bytes at the start of the file.
+    fn from_magic_bytes(bytes: &[u8]) -> Compression {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if bytes.starts_with(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Opens the file at the given path as a stream that is decompressed on the fly.
+///
+/// The compression is detected from the file extension and, if the extension is not conclusive, from the magic bytes.
+fn open_decompressed(path: &str) -> Result<Box<dyn Read>, Box<dyn Error>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let compression = match Compression::from_extension(path) {
+        Compression::None => Compression::from_magic_bytes(reader.fill_buf()?),
+        compression => compression,
+    };
+    let reader: Box<dyn Read> = match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+    };
+    Ok(reader)
+}
+
+impl Format {
+    /// Detects the format from the file extension, ignoring the extension of a compression codec. Defaults to CSV.
+    pub fn from_path(path: &str) -> Format {
+        let mut path = path.to_lowercase();
+        for extension in [".gz", ".zst", ".zstd", ".xz"].iter() {
+            if path.ends_with(extension) {
+                path.truncate(path.len() - extension.len());
+            }
+        }
+        if path.ends_with(".parquet") || path.ends_with(".pq") {
+            Format::Parquet
+        } else if path.ends_with(".arrow") || path.ends_with(".ipc") || path.ends_with(".feather") {
+            Format::ArrowIpc
+        } else {
+            Format::Csv
+        }
+    }
+}
+
+/// Opens a reader for the dataset at the given path that yields (at least) the given columns.
+///
+/// Compressed files are only supported for CSV since columnar formats are compressed internally.
+pub fn open(path: &str, columns: &[String]) -> Result<Box<dyn RowReader>, Box<dyn Error>> {
+    let format = Format::from_path(path);
+    if format != Format::Csv && Compression::from_extension(path) != Compression::None {
+        return Err(format!("Error: Compressed {:?} files are not supported.", format).into());
+    }
+    match format {
+        Format::Csv => Ok(Box::new(CsvReader::open(path)?)),
+        #[cfg(feature = "columnar")]
+        Format::Parquet => Ok(Box::new(BatchReader::open_parquet(path, columns)?)),
+        #[cfg(feature = "columnar")]
+        Format::ArrowIpc => Ok(Box::new(BatchReader::open_ipc(path, columns)?)),
+        #[cfg(not(feature = "columnar"))]
+        format => {
+            let _ = columns;
+            Err(format!(
+                "Error: Reading {:?} files requires building with the \"columnar\" feature.",
+                format
+            )
+            .into())
+        }
+    }
+}
+
+/// Reader for (possibly compressed) CSV files.
+struct CsvReader {
+    reader: csv::Reader<Box<dyn Read>>,
+    headers: StringRecord,
+}
+
+impl CsvReader {
+    fn open(path: &str) -> Result<CsvReader, Box<dyn Error>> {
+        let mut reader = csv::Reader::from_reader(open_decompressed(path)?);
+        let headers = reader.headers()?.clone();
+        Ok(CsvReader {
+            reader: reader,
+            headers: headers,
+        })
+    }
+}
+
+impl RowReader for CsvReader {
+    fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    fn read_row(&mut self, row: &mut StringRecord) -> Result<bool, Box<dyn Error>> {
+        Ok(self.reader.read_record(row)?)
+    }
+}
+
+/// Reader for columnar formats that are decoded into Arrow record batches.
+#[cfg(feature = "columnar")]
+struct BatchReader {
+    batches: Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>>>,
+    batch: Option<RecordBatch>,
+    row_idx: usize,
+    headers: StringRecord,
+}
+
+#[cfg(feature = "columnar")]
+impl BatchReader {
+    /// Returns the indices of the given columns within the list of all column names.
+    fn projection<'a>(
+        names: impl Iterator<Item = &'a String>,
+        columns: &[String],
+    ) -> Vec<usize> {
+        names
+            .enumerate()
+            .filter(|(_, name)| columns.contains(name))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    fn open_parquet(path: &str, columns: &[String]) -> Result<BatchReader, Box<dyn Error>> {
+        use parquet::arrow::{arrow_reader::ParquetRecordBatchReaderBuilder, ProjectionMask};
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+        let file_schema = builder.schema().clone();
+        let projection =
+            BatchReader::projection(file_schema.fields().iter().map(|field| field.name()), columns);
+        let headers = projection
+            .iter()
+            .map(|idx| file_schema.field(*idx).name().as_str())
+            .collect();
+        let mask = ProjectionMask::roots(builder.parquet_schema(), projection);
+        let reader = builder.with_projection(mask).build()?;
+        Ok(BatchReader {
+            batches: Box::new(reader),
+            batch: None,
+            row_idx: 0,
+            headers: headers,
+        })
+    }
+
+    fn open_ipc(path: &str, columns: &[String]) -> Result<BatchReader, Box<dyn Error>> {
+        use arrow::ipc::reader::FileReader;
+
+        let file_schema = FileReader::try_new(File::open(path)?, None)?.schema();
+        let projection =
+            BatchReader::projection(file_schema.fields().iter().map(|field| field.name()), columns);
+        let headers = projection
+            .iter()
+            .map(|idx| file_schema.field(*idx).name().as_str())
+            .collect();
+        let reader = FileReader::try_new(File::open(path)?, Some(projection))?;
+        Ok(BatchReader {
+            batches: Box::new(reader),
+            batch: None,
+            row_idx: 0,
+            headers: headers,
+        })
+    }
+}
+
+#[cfg(feature = "columnar")]
+impl RowReader for BatchReader {
+    fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    fn read_row(&mut self, row: &mut StringRecord) -> Result<bool, Box<dyn Error>> {
+        // Advance to the next non-empty batch once the current one is exhausted
+        while self.batch.as_ref().map_or(true, |batch| self.row_idx >= batch.num_rows()) {
+            match self.batches.next() {
+                Some(batch) => {
+                    self.batch = Some(batch?);
+                    self.row_idx = 0;
+                }
+                None => return Ok(false),
+            }
+        }
+        let batch = self.batch.as_ref().unwrap();
+        row.clear();
+        for column in batch.columns() {
+            row.push_field(&array_value_to_string(column, self.row_idx)?);
+        }
+        self.row_idx += 1;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression as GzCompression};
+    use std::{fs, io::Write};
+    use xz2::write::XzEncoder;
+
+    const CSV: &str = "user_id,timestamp\nuser_1,0\nuser_2,30\n";
+
+    fn compress(compression: Compression, data: &[u8]) -> Vec<u8> {
+        match compression {
+            Compression::None => data.to_vec(),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), GzCompression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Compression::Zstd => zstd::encode_all(data, 0).unwrap(),
+            Compression::Xz => {
+                let mut encoder = XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+
+    fn read_rows(path: &str) -> Vec<Vec<String>> {
+        let mut reader = open(path, &[]).unwrap();
+        assert_eq!(reader.headers(), vec!["user_id", "timestamp"]);
+        let mut row = StringRecord::new();
+        let mut rows = Vec::new();
+        while reader.read_row(&mut row).unwrap() {
+            rows.push(row.iter().map(String::from).collect());
+        }
+        rows
+    }
+
+    #[test]
+    fn reads_compressed_csv() {
+        let dir = tempfile::tempdir().unwrap();
+        let expected = vec![vec!["user_1", "0"], vec!["user_2", "30"]];
+        let formats = [
+            (Compression::None, "data.csv"),
+            (Compression::Gzip, "data.csv.gz"),
+            (Compression::Zstd, "data.csv.zst"),
+            (Compression::Xz, "data.csv.xz"),
+        ];
+        for (compression, name) in formats.iter() {
+            let data = compress(*compression, CSV.as_bytes());
+            // Without a conclusive extension, the compression is detected from the magic bytes
+            for name in [*name, "data.csv.bin"].iter() {
+                let path = dir.path().join(name);
+                fs::write(&path, &data).unwrap();
+                assert_eq!(read_rows(path.to_str().unwrap()), expected, "{:?} {}", compression, name);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_concatenated_gzip_members() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data.csv.gz");
+        let mut data = compress(Compression::Gzip, b"user_id,timestamp\nuser_1,0\n");
+        data.extend(compress(Compression::Gzip, b"user_2,30\n"));
+        fs::write(&path, &data).unwrap();
+        assert_eq!(read_rows(path.to_str().unwrap()).len(), 2);
+    }
+
+    #[test]
+    fn detects_format_and_compression() {
+        assert_eq!(Format::from_path("data.CSV.GZ"), Format::Csv);
+        assert_eq!(Format::from_path("data.parquet"), Format::Parquet);
+        assert_eq!(Format::from_path("data.feather"), Format::ArrowIpc);
+        assert_eq!(Compression::from_extension("data.csv.zstd"), Compression::Zstd);
+        assert_eq!(Compression::from_magic_bytes(b"user_id"), Compression::None);
+        assert!(open("data.parquet.gz", &[]).is_err());
+    }
+}
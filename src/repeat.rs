@@ -0,0 +1,229 @@
+use crate::cli::Config;
+use crate::sample::SamplingMaps;
+use crate::trace::{Column, Trace};
+use crate::utils::{self, Scores};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::Path,
+};
+
+/// Evaluation function of an approach, i.e. `frequency::evaluation::eval` or `sequence::evaluation::eval`.
+pub type EvalFn<C> = fn(
+    &Config,
+    &BTreeMap<u32, Vec<Trace<C>>>,
+    &HashMap<u32, Vec<usize>>,
+    &HashMap<u32, Vec<usize>>,
+    &HashMap<u32, usize>,
+) -> Scores;
+
+/// Two-sided 95% quantiles of Student's t-distribution for 1 to 30 degrees of freedom.
+const T_QUANTILES: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160, 2.145, 2.131,
+    2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056, 2.052, 2.048, 2.045, 2.042,
+];
+
+/// Mean of a score across repeats with a 95% confidence interval.
+#[derive(Debug, Serialize)]
+pub struct Aggregate {
+    pub mean: f64,
+    /// Sample standard deviation across repeats
+    pub std: f64,
+    pub ci_lower: f64,
+    pub ci_upper: f64,
+}
+
+/// Scores of the linkage attacks on a single partition of the target users of a repeat.
+#[derive(Debug, Serialize)]
+pub struct Partition {
+    pub repeat: usize,
+    pub seed: u64,
+    pub partition: usize,
+    #[serde(flatten)]
+    pub scores: Scores,
+}
+
+/// Record of a repeated evaluation with the scores of each partition and their aggregates across repeats.
+///
+/// The partitions of a repeat share their sampling maps, so they are not independent samples and, unlike the folds of
+/// a cross-validation, do not hold out any data. The aggregates are therefore computed over the scores of the
+/// repeats, each of which covers the attacks on all partitions.
+#[derive(Debug, Serialize)]
+pub struct Experiment {
+    pub approach: String,
+    pub path: String,
+    pub fields: Vec<String>,
+    pub num_partitions: usize,
+    pub num_repeats: usize,
+    pub partitions: Vec<Partition>,
+    pub top_1: Aggregate,
+    pub top_10: Aggregate,
+    pub top_10_percent: Aggregate,
+    pub mean_reciprocal_rank: Aggregate,
+}
+
+impl Aggregate {
+    /// Aggregates the values of the repeats. The confidence interval is based on Student's t-distribution and is
+    /// undefined for less than two repeats.
+    fn new(values: &[f64]) -> Aggregate {
+        let mean = utils::mean(values);
+        let n = values.len();
+        if n < 2 {
+            return Aggregate {
+                mean: mean,
+                std: f64::NAN,
+                ci_lower: f64::NAN,
+                ci_upper: f64::NAN,
+            };
+        }
+        let std = (values.iter().map(|value| (value - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt();
+        let t = match T_QUANTILES.get(n - 2) {
+            Some(t) => *t,
+            None => 1.96,
+        };
+        let half_width = t * std / (n as f64).sqrt();
+        Aggregate {
+            mean: mean,
+            std: std,
+            ci_lower: mean - half_width,
+            ci_upper: mean + half_width,
+        }
+    }
+}
+
+impl Experiment {
+    /// Writes the experiment record to a JSON file.
+    pub fn write_to_path(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+}
+
+/// Repeats the sampling and the evaluation with consecutive seeds starting at the configured seed. Every repeat
+/// samples from the full population, so the repeats are independent samples of users and traces and the confidence
+/// intervals are computed across them, which requires at least two repeats.
+///
+/// In each repeat, the sampled target users are split into disjoint partitions that are attacked separately, while
+/// all users remain candidates. Partitions are evaluated in batches of the configured number of parallel partitions,
+/// since every partition holds the rankings of its attacks in memory.
+pub fn repeat<C: Column + Sync>(
+    config: &Config,
+    user_to_vector_map: &BTreeMap<u32, Vec<Trace<C>>>,
+    eval: EvalFn<C>,
+) -> Result<Experiment, Box<dyn Error>> {
+    let params = config.repeat.as_ref().unwrap();
+    if params.num_partitions == 0 || params.parallel_partitions == 0 {
+        return Err("Error: The number of partitions and parallel partitions must be positive.".into());
+    }
+    if params.num_repeats < 2 {
+        return Err("Error: The confidence intervals require at least two repeats.".into());
+    }
+
+    let mut partitions: Vec<Partition> = Vec::with_capacity(params.num_partitions * params.num_repeats);
+    for repeat in 0..params.num_repeats {
+        let seed = config.seed + repeat as u64;
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Users without observed traces are skipped by the evaluation, so the population is left untouched
+        let maps = SamplingMaps::sample(config, user_to_vector_map, &mut rng)?;
+        let (user_to_target_idx_map, user_to_sample_idx_map, user_to_test_idx_map, stratification) =
+            maps.into_maps();
+
+        let mut users: Vec<u32> = user_to_target_idx_map.keys().cloned().collect();
+        if users.len() < params.num_partitions {
+            return Err(format!(
+                "Error: {:?} sampled users cannot be split into {:?} partitions.",
+                users.len(),
+                params.num_partitions
+            )
+            .into());
+        }
+        users.sort_unstable();
+        users.shuffle(&mut rng);
+        let partition_maps: Vec<HashMap<u32, Vec<usize>>> = (0..params.num_partitions)
+            .map(|partition| {
+                users
+                    .iter()
+                    .skip(partition)
+                    .step_by(params.num_partitions)
+                    .map(|user| (*user, user_to_target_idx_map[user].clone()))
+                    .collect()
+            })
+            .collect();
+
+        for (batch_idx, batch) in partition_maps.chunks(params.parallel_partitions).enumerate() {
+            let batch_scores: Vec<Scores> = batch
+                .par_iter()
+                .map(|partition_target_idx_map| {
+                    let mut scores = eval(
+                        config,
+                        user_to_vector_map,
+                        partition_target_idx_map,
+                        &user_to_sample_idx_map,
+                        &user_to_test_idx_map,
+                    );
+                    scores.add_strata(stratification.as_ref());
+                    scores
+                })
+                .collect();
+            for (idx, scores) in batch_scores.into_iter().enumerate() {
+                let partition = batch_idx * params.parallel_partitions + idx;
+                log::info!(
+                    "Repeat {:?}, partition {:?}: Rank 1: {:?}, Top 10: {:?}, Top 10 Percent: {:?}",
+                    repeat,
+                    partition,
+                    scores.top_1,
+                    scores.top_10,
+                    scores.top_10_percent
+                );
+                partitions.push(Partition {
+                    repeat: repeat,
+                    seed: seed,
+                    partition: partition,
+                    scores: scores,
+                });
+            }
+        }
+    }
+
+    // The score of a repeat is the mean over all of its attacks, so the partitions are weighted by their size
+    let aggregate = |score: fn(&Scores) -> f64| {
+        let values: Vec<f64> = (0..params.num_repeats)
+            .map(|repeat| {
+                let (total, num_attacks) = partitions
+                    .iter()
+                    .filter(|partition| partition.repeat == repeat)
+                    .fold((0., 0), |(total, num_attacks), partition| {
+                        let n = partition.scores.num_attacks;
+                        (total + score(&partition.scores) * n as f64, num_attacks + n)
+                    });
+                total / num_attacks as f64
+            })
+            .collect();
+        Aggregate::new(&values)
+    };
+    let top_1 = aggregate(|scores| scores.top_1);
+    let top_10 = aggregate(|scores| scores.top_10);
+    let top_10_percent = aggregate(|scores| scores.top_10_percent);
+    let mean_reciprocal_rank = aggregate(|scores| scores.mean_reciprocal_rank);
+    Ok(Experiment {
+        approach: config.approach.clone(),
+        path: config.path.clone(),
+        fields: config.schema.attribute_names(&config.fields),
+        num_partitions: params.num_partitions,
+        num_repeats: params.num_repeats,
+        partitions: partitions,
+        top_1: top_1,
+        top_10: top_10,
+        top_10_percent: top_10_percent,
+        mean_reciprocal_rank: mean_reciprocal_rank,
+    })
+}
@@ -0,0 +1,189 @@
+use crate::cli::Config;
+use crate::parse::Dataset;
+use crate::trace::Column;
+
+use ordered_float::OrderedFloat;
+use serde::Serialize;
+use std::{collections::HashSet, error::Error, fs::File, io::BufWriter, path::Path};
+
+/// Summary statistics of a distribution.
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p95: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+/// Quality indicators of a single attribute.
+#[derive(Debug, Serialize)]
+pub struct AttributeReport {
+    pub name: String,
+    /// Number of distinct non-empty values in the remaining traces
+    pub cardinality: usize,
+    /// Share of points with an empty value in the input
+    pub empty_share: f64,
+}
+
+/// Data quality report of a dataset after segmentation.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub path: String,
+    pub num_points: usize,
+    pub num_skipped_points: usize,
+    pub num_monotonicity_violations: usize,
+    pub num_users: usize,
+    pub num_dropped_users: usize,
+    pub num_traces: usize,
+    pub num_short_traces: usize,
+    pub num_long_traces: usize,
+    pub num_dropped_user_traces: usize,
+    pub trace_lengths: Summary,
+    pub trace_durations: Summary,
+    pub traces_per_user: Summary,
+    pub attributes: Vec<AttributeReport>,
+}
+
+impl Summary {
+    /// Summarizes the given values. Percentiles are determined with the nearest-rank method.
+    fn new(mut values: Vec<f64>) -> Summary {
+        values.sort_unstable_by_key(|value| OrderedFloat(*value));
+        let percentile = |p: f64| -> f64 {
+            if values.is_empty() {
+                return f64::NAN;
+            }
+            let rank = (p * values.len() as f64).ceil() as usize;
+            values[rank.max(1) - 1]
+        };
+        Summary {
+            count: values.len(),
+            min: percentile(0.0),
+            p25: percentile(0.25),
+            median: percentile(0.5),
+            p75: percentile(0.75),
+            p95: percentile(0.95),
+            max: percentile(1.0),
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+        }
+    }
+}
+
+impl Report {
+    /// Generates the report from a parsed dataset for the selected fields.
+    pub fn new<C: Column>(config: &Config, dataset: &Dataset<C>) -> Report {
+        let traces = || dataset.user_to_traces_map.values().flat_map(|traces| traces.iter());
+        let stats = &dataset.stats;
+
+        let attributes = config
+            .fields
+            .iter()
+            .map(|field| {
+                let dictionary = &dataset.dictionaries[*field];
+                let mut values: HashSet<u32> = HashSet::new();
+                for trace in traces() {
+                    trace.columns[*field].for_each_value(|value, count| {
+                        if count > 0 && !dictionary[value as usize].is_empty() {
+                            values.insert(value);
+                        }
+                    });
+                }
+                AttributeReport {
+                    name: config.schema.attributes[*field].name.clone(),
+                    cardinality: values.len(),
+                    empty_share: stats.num_empty_values[*field] as f64 / stats.num_points as f64,
+                }
+            })
+            .collect();
+
+        Report {
+            path: config.path.clone(),
+            num_points: stats.num_points,
+            num_skipped_points: stats.num_skipped_points,
+            num_monotonicity_violations: stats.num_monotonicity_violations,
+            num_users: dataset.user_to_traces_map.len(),
+            num_dropped_users: stats.num_dropped_users,
+            num_traces: traces().count(),
+            num_short_traces: stats.num_short_traces,
+            num_long_traces: stats.num_long_traces,
+            num_dropped_user_traces: stats.num_dropped_user_traces,
+            trace_lengths: Summary::new(traces().map(|trace| trace.num_points as f64).collect()),
+            trace_durations: Summary::new(
+                traces()
+                    .map(|trace| trace.end_time - trace.start_time)
+                    .collect(),
+            ),
+            traces_per_user: Summary::new(
+                dataset
+                    .user_to_traces_map
+                    .values()
+                    .map(|traces| traces.len() as f64)
+                    .collect(),
+            ),
+            attributes: attributes,
+        }
+    }
+
+    /// Writes the report to a JSON file.
+    pub fn write_to_path(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Prints the report as human-readable tables.
+    pub fn print_table(&self) {
+        println!("Data quality report for {}", self.path);
+        println!();
+        let counts = [
+            ("points", self.num_points),
+            ("points skipped (missing values)", self.num_skipped_points),
+            ("timestamp monotonicity violations", self.num_monotonicity_violations),
+            ("users", self.num_users),
+            ("users dropped (min_num_traces)", self.num_dropped_users),
+            ("traces", self.num_traces),
+            ("traces dropped (min_trace_len)", self.num_short_traces),
+            ("traces dropped (max_trace_duration)", self.num_long_traces),
+            ("traces of dropped users", self.num_dropped_user_traces),
+        ];
+        for (name, count) in counts.iter() {
+            println!("{:<36} {:>12}", name, count);
+        }
+
+        println!();
+        println!(
+            "{:<20} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "distribution", "count", "min", "p25", "median", "p75", "p95", "max", "mean"
+        );
+        let summaries = [
+            ("trace length", &self.trace_lengths),
+            ("trace duration [s]", &self.trace_durations),
+            ("traces per user", &self.traces_per_user),
+        ];
+        for (name, summary) in summaries.iter() {
+            println!(
+                "{:<20} {:>8} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1} {:>10.1}",
+                name,
+                summary.count,
+                summary.min,
+                summary.p25,
+                summary.median,
+                summary.p75,
+                summary.p95,
+                summary.max,
+                summary.mean
+            );
+        }
+
+        println!();
+        println!("{:<20} {:>12} {:>12}", "attribute", "cardinality", "empty share");
+        for attr in self.attributes.iter() {
+            println!("{:<20} {:>12} {:>12.4}", attr.name, attr.cardinality, attr.empty_share);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+use crate::cli::Config;
+use crate::stats;
+use crate::store::{Store, StoredRun};
+
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+};
+
+/// Scores listed for every run.
+const SCORES: [&str; 5] = ["top_1", "top_10", "top_10_percent", "mean_reciprocal_rank", "median_rank"];
+
+/// Looks up a configuration value by its key, where the keys of nested values are joined by dots, e.g.
+/// `repeat.num_repeats`.
+fn lookup<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    key.split('.').try_fold(value, |value, part| value.get(part))
+}
+
+/// Formats a configuration value for display. Strings are shown without quotes and lists of plain values are
+/// separated by commas.
+fn display(value: Option<&Value>) -> String {
+    match value {
+        None => "-".to_string(),
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(values)) if values.iter().all(|value| !value.is_array() && !value.is_object()) => values
+            .iter()
+            .map(|value| display(Some(value)))
+            .collect::<Vec<String>>()
+            .join(","),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Returns true if a configuration value equals the expected value given on the command line. Numbers are compared
+/// by value, so that `1800` matches `1800.0`.
+fn matches(value: Option<&Value>, expected: &str) -> bool {
+    match value {
+        None => false,
+        Some(Value::Number(number)) => expected.parse::<f64>().ok() == number.as_f64(),
+        Some(Value::Bool(b)) => expected.parse::<bool>().ok() == Some(*b),
+        Some(value) => display(Some(value)) == expected,
+    }
+}
+
+/// Shortens a run id or timestamp for display, leaving values that are shorter or not split at a character boundary
+/// as they are.
+fn short(value: &str, len: usize) -> String {
+    value.get(..len).unwrap_or(value).to_string()
+}
+
+fn score(run: &StoredRun, name: &str) -> f64 {
+    run.scores.get(name).and_then(Value::as_f64).unwrap_or(f64::NAN)
+}
+
+/// Flattens a configuration into its keys and displayed values.
+fn flatten(prefix: &str, value: &Value, flat: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter() {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, flat);
+            }
+        }
+        value => {
+            flat.insert(prefix.to_string(), display(Some(value)));
+        }
+    }
+}
+
+/// Logs a table with columns as wide as their widest value.
+fn log_table(header: &[String], rows: &[Vec<String>]) {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|idx| rows.iter().map(|row| row[idx].len()).chain(Some(header[idx].len())).max().unwrap())
+        .collect();
+    let format_row = |row: &[String]| {
+        row.iter()
+            .zip(widths.iter())
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    log::info!("{}", format_row(header));
+    for row in rows.iter() {
+        log::info!("{}", format_row(row));
+    }
+}
+
+/// Lists the runs with the given configuration values and their scores.
+fn list(runs: &[StoredRun], columns: &[String]) {
+    let header: Vec<String> = ["run_id", "timestamp"]
+        .iter()
+        .map(|name| name.to_string())
+        .chain(columns.iter().cloned())
+        .chain(SCORES.iter().map(|name| name.to_string()))
+        .collect();
+    let rows: Vec<Vec<String>> = runs
+        .iter()
+        .map(|run| {
+            [short(&run.run_id, 8), short(&run.timestamp, 19)]
+                .iter()
+                .cloned()
+                .chain(columns.iter().map(|column| display(lookup(&run.config, column))))
+                .chain(SCORES.iter().map(|name| format!("{:.4}", score(run, name))))
+                .collect()
+        })
+        .collect();
+    log_table(&header, &rows);
+}
+
+/// Compares the runs grouped by the value of a configuration key by the mean of their scores per group.
+fn group(runs: &[StoredRun], key: &str) {
+    let mut groups: BTreeMap<String, Vec<&StoredRun>> = BTreeMap::new();
+    for run in runs.iter() {
+        groups.entry(display(lookup(&run.config, key))).or_default().push(run);
+    }
+    let header: Vec<String> = [key, "runs"]
+        .iter()
+        .map(|name| name.to_string())
+        .chain(SCORES.iter().map(|name| name.to_string()))
+        .collect();
+    let rows: Vec<Vec<String>> = groups
+        .iter()
+        .map(|(value, group_runs)| {
+            [value.clone(), group_runs.len().to_string()]
+                .iter()
+                .cloned()
+                .chain(SCORES.iter().map(|name| {
+                    let total: f64 = group_runs.iter().map(|run| score(run, name)).sum();
+                    format!("{:.4}", total / group_runs.len() as f64)
+                }))
+                .collect()
+        })
+        .collect();
+    log_table(&header, &rows);
+}
+
+/// Shows the configuration values and scores in which two runs differ and whether their attacks were paired.
+fn diff(store: &Store, first: &str, second: &str) -> Result<(), Box<dyn Error>> {
+    let runs = [store.find_run(first)?, store.find_run(second)?];
+    let ids: Vec<String> = runs.iter().map(|run| short(&run.run_id, 8)).collect();
+
+    if runs[0].git_commit != runs[1].git_commit {
+        log::info!(
+            "The runs were built from the commits {:?} and {:?}.",
+            runs[0].git_commit,
+            runs[1].git_commit
+        );
+    }
+
+    let mut flat = [BTreeMap::new(), BTreeMap::new()];
+    for (run, flat) in runs.iter().zip(flat.iter_mut()) {
+        flatten("", &run.config, flat);
+    }
+    let keys: BTreeSet<&String> = flat[0].keys().chain(flat[1].keys()).collect();
+    let missing = "-".to_string();
+    let rows: Vec<Vec<String>> = keys
+        .into_iter()
+        .filter(|key| flat[0].get(*key) != flat[1].get(*key))
+        .map(|key| {
+            vec![
+                key.clone(),
+                flat[0].get(key).unwrap_or(&missing).clone(),
+                flat[1].get(key).unwrap_or(&missing).clone(),
+            ]
+        })
+        .collect();
+    log::info!("The configurations differ in {:?} keys.", rows.len());
+    log_table(&["key".to_string(), ids[0].clone(), ids[1].clone()], &rows);
+
+    let rows: Vec<Vec<String>> = SCORES
+        .iter()
+        .map(|name| {
+            let (a, b) = (score(&runs[0], name), score(&runs[1], name));
+            vec![
+                name.to_string(),
+                format!("{:.4}", a),
+                format!("{:.4}", b),
+                format!("{:+.4}", b - a),
+            ]
+        })
+        .collect();
+    log_table(
+        &["score".to_string(), ids[0].clone(), ids[1].clone(), "difference".to_string()],
+        &rows,
+    );
+
+    // The attacks on a user are only paired if both runs sampled the same traces
+    let maps = [store.sampling_maps(&runs[0].run_id)?, store.sampling_maps(&runs[1].run_id)?];
+    match &maps {
+        [Some(a), Some(b)] if serde_json::to_value(a)? == serde_json::to_value(b)? => {
+            log::info!("Both runs use the same sampling maps.");
+        }
+        [Some(_), Some(_)] => {
+            log::warn!("The runs use different sampling maps, so their attacks are not paired. Skipping the test.");
+            return Ok(());
+        }
+        _ => {
+            log::warn!("The sampling maps of the runs are unknown, so the attacks are not paired. Skipping the test.");
+            return Ok(());
+        }
+    }
+
+    // Pair the attacks on the target users present in both runs
+    let outcomes: BTreeMap<String, bool> = store
+        .outcomes(&runs[0].run_id)?
+        .into_iter()
+        .filter(|outcome| outcome.is_present)
+        .map(|outcome| (outcome.user_id, outcome.is_top_1))
+        .collect();
+    let (baseline, candidate): (Vec<bool>, Vec<bool>) = store
+        .outcomes(&runs[1].run_id)?
+        .into_iter()
+        .filter(|outcome| outcome.is_present)
+        .filter_map(|outcome| outcomes.get(&outcome.user_id).map(|is_top_1| (*is_top_1, outcome.is_top_1)))
+        .unzip();
+    if !baseline.is_empty() {
+        let test = stats::mcnemar(&baseline, &candidate);
+        log::info!(
+            "Rank 1 on {:?} paired attacks: {:?} only linked by {}, {:?} only linked by {}, McNemar p-value {:.4}.",
+            baseline.len(),
+            test.num_baseline_only,
+            ids[0],
+            test.num_candidate_only,
+            ids[1],
+            test.p_value
+        );
+    }
+    Ok(())
+}
+
+/// Lists, groups or compares the runs of the experiment store.
+pub fn results(config: &Config) -> Result<(), Box<dyn Error>> {
+    let params = config.results.as_ref().unwrap();
+    let path = match &config.store {
+        Some(path) => path,
+        None => return Err("Error: The results command requires --store.".into()),
+    };
+    let store = Store::open(path)?;
+    if let Some((first, second)) = &params.diff {
+        return diff(&store, first, second);
+    }
+
+    let runs: Vec<StoredRun> = store
+        .runs()?
+        .into_iter()
+        .filter(|run| {
+            params
+                .filters
+                .iter()
+                .all(|(key, value)| matches(lookup(&run.config, key), value))
+        })
+        .collect();
+    log::info!("{:?} runs match the filters.", runs.len());
+    match &params.group_by {
+        Some(key) => group(&runs, key),
+        None => list(&runs, &params.columns),
+    }
+    Ok(())
+}
@@ -1,51 +1,331 @@
+use crate::cli::Config;
+use crate::split::{self, Split};
+use crate::strata::Stratification;
+use crate::trace::{Column, Trace};
+use crate::users::UserIds;
+
 use rand::{prelude::SliceRandom, seq::IteratorRandom, Rng};
-use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+/// Target, observed and test trace indices per user and the strata as taken by the evaluation.
+pub type AttackMaps = (
+    HashMap<u32, Vec<usize>>,
+    HashMap<u32, Vec<usize>>,
+    HashMap<u32, usize>,
+    Option<Stratification>,
+);
+
+/// The sampled target, observed and test trace indices per user, which fully determine the linkage attacks.
+///
+/// The maps are stored in tree maps so that exported files list the users in a fixed order. Internally, the users
+/// are identified by their sequential ids. These depend on the order of the rows and on the users that are filtered
+/// out, so exported maps identify the users by their original ids instead.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SamplingMaps<U: Ord = u32> {
+    pub user_to_target_idx_map: BTreeMap<U, Vec<usize>>,
+    pub user_to_sample_idx_map: BTreeMap<U, Vec<usize>>,
+    pub user_to_test_idx_map: BTreeMap<U, usize>,
+    /// Strata of the users if the users are sampled by activity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stratification: Option<Stratification<U>>,
+}
+
+/// Replaces the users of a map by the given function. Fails if the function fails or if two users are replaced by the
+/// same one.
+fn map_users<U, V, T, F>(map: &BTreeMap<U, T>, f: &mut F) -> Result<BTreeMap<V, T>, Box<dyn Error>>
+where
+    V: Ord + std::fmt::Debug,
+    T: Clone,
+    F: FnMut(&U) -> Result<V, Box<dyn Error>>,
+{
+    let mut mapped: BTreeMap<V, T> = BTreeMap::new();
+    for (user, value) in map.iter() {
+        let user = f(user)?;
+        if mapped.contains_key(&user) {
+            return Err(format!("Error: The user {:?} occurs several times in the sampling maps.", user).into());
+        }
+        mapped.insert(user, value.clone());
+    }
+    Ok(mapped)
+}
+
+impl<U: Ord> SamplingMaps<U> {
+    /// Replaces the users in all maps by the given function.
+    fn map_users<V, F>(&self, mut f: F) -> Result<SamplingMaps<V>, Box<dyn Error>>
+    where
+        V: Ord + std::fmt::Debug,
+        F: FnMut(&U) -> Result<V, Box<dyn Error>>,
+    {
+        let stratification = match &self.stratification {
+            Some(stratification) => Some(Stratification {
+                activity: stratification.activity.clone(),
+                strata: stratification.strata.clone(),
+                user_to_stratum_map: map_users(&stratification.user_to_stratum_map, &mut f)?,
+            }),
+            None => None,
+        };
+        Ok(SamplingMaps {
+            user_to_target_idx_map: map_users(&self.user_to_target_idx_map, &mut f)?,
+            user_to_sample_idx_map: map_users(&self.user_to_sample_idx_map, &mut f)?,
+            user_to_test_idx_map: map_users(&self.user_to_test_idx_map, &mut f)?,
+            stratification: stratification,
+        })
+    }
+}
+
+/// Returns true if the path has a pickle extension (`.pkl` or `.pickle`), otherwise the maps are stored as JSON.
+fn is_pickle(path: &str) -> bool {
+    matches!(Path::new(path).extension().and_then(|ext| ext.to_str()), Some("pkl") | Some("pickle"))
+}
+
+impl SamplingMaps {
+    pub fn new(
+        user_to_target_idx_map: BTreeMap<u32, Vec<usize>>,
+        user_to_sample_idx_map: BTreeMap<u32, Vec<usize>>,
+        user_to_test_idx_map: BTreeMap<u32, usize>,
+    ) -> SamplingMaps {
+        SamplingMaps {
+            user_to_target_idx_map: user_to_target_idx_map,
+            user_to_sample_idx_map: user_to_sample_idx_map,
+            user_to_test_idx_map: user_to_test_idx_map,
+            stratification: None,
+        }
+    }
+
+    /// Splits the history of each user and samples the users and traces of the linkage attacks.
+    pub fn sample<C: Column, R: Rng>(
+        config: &Config,
+        user_to_vector_map: &BTreeMap<u32, Vec<Trace<C>>>,
+        rng: &mut R,
+    ) -> Result<SamplingMaps, Box<dyn Error>> {
+        log::info!("Splitting the history of each user...");
+        let user_to_split_map = split::split_histories(config, user_to_vector_map, rng)?;
+
+        let stratification = match config.stratify.as_str() {
+            "none" => None,
+            activity => {
+                log::info!("Stratifying users by {}...", activity);
+                let users = attackable_users(&user_to_split_map, config.target_trace_sample_size);
+                Some(Stratification::new(activity, &users, user_to_vector_map, config.num_strata))
+            }
+        };
+
+        log::info!("Sampling users...");
+        let user_to_target_idx_map: BTreeMap<u32, Vec<usize>> = gen_user_to_target_idx_map(
+            &user_to_split_map,
+            stratification.as_ref(),
+            rng,
+            config.user_sample_size,
+            config.target_trace_sample_size,
+        );
+
+        log::info!("Sampling traces per user...");
+        let user_to_sample_idx_map: BTreeMap<u32, Vec<usize>> = gen_user_to_sample_idx_map(
+            &user_to_split_map,
+            &user_to_target_idx_map,
+            rng,
+            config.trace_sample_size,
+        );
+
+        log::info!("Sampling a single test trace per user...");
+        let user_to_test_idx_map: BTreeMap<u32, usize> =
+            gen_user_to_test_idx_map(&user_to_sample_idx_map, rng);
+
+        let mut maps = SamplingMaps::new(
+            user_to_target_idx_map,
+            user_to_sample_idx_map,
+            user_to_test_idx_map,
+        );
+        maps.stratification = stratification;
+        if config.open_world_fraction > 0. {
+            maps.withhold_targets(config.open_world_fraction, rng);
+        }
+        Ok(maps)
+    }
+
+    /// Withholds a fraction of the target users from the candidates of the linkage attacks by removing their observed
+    /// traces, so that the attacks on these users cannot succeed (open world).
+    pub fn withhold_targets<R: Rng>(&mut self, fraction: f64, rng: &mut R) {
+        let num_withheld = (fraction * self.user_to_target_idx_map.len() as f64).round() as usize;
+        log::info!("Withholding {:?} target users from the candidates...", num_withheld);
+        // The target users are stored in a tree map, so that the withheld users are reproducible
+        let users: Vec<u32> = self.user_to_target_idx_map.keys().cloned().collect();
+        for user in users.iter().choose_multiple(rng, num_withheld) {
+            self.user_to_sample_idx_map.remove(user);
+            self.user_to_test_idx_map.remove(user);
+        }
+    }
+
+    /// Removes the users that are neither candidates nor targets from the traces.
+    pub fn retain_observed_users<T>(&self, user_to_vector_map: &mut BTreeMap<u32, Vec<T>>) {
+        let num_users = user_to_vector_map.len();
+        user_to_vector_map.retain(|user, _| {
+            self.user_to_sample_idx_map.contains_key(user) || self.user_to_target_idx_map.contains_key(user)
+        });
+        if user_to_vector_map.len() < num_users {
+            log::warn!(
+                "Removed {:?} users without observed traces.",
+                num_users - user_to_vector_map.len()
+            );
+        }
+    }
+
+    /// Returns the maps with the original user ids, as they are exported.
+    pub fn to_original(&self, user_ids: &UserIds) -> Result<SamplingMaps<String>, Box<dyn Error>> {
+        self.map_users(|user| Ok(user_ids.original(*user).to_string()))
+    }
+
+    /// Returns the maps with the sequential user ids of the dataset. Fails if a user does not exist in the dataset.
+    pub fn from_original(maps: &SamplingMaps<String>, user_ids: &UserIds) -> Result<SamplingMaps, Box<dyn Error>> {
+        maps.map_users(|user| {
+            user_ids
+                .id(user)
+                .ok_or_else(|| format!("Error: User {:?} of the sampling maps does not exist.", user).into())
+        })
+    }
+
+    /// Reads the maps with the original user ids from a JSON or pickle file.
+    pub fn from_path(path: &str, user_ids: &UserIds) -> Result<SamplingMaps, Box<dyn Error>> {
+        let reader = BufReader::new(File::open(path)?);
+        let maps: SamplingMaps<String> = if is_pickle(path) {
+            serde_pickle::from_reader(reader, Default::default())?
+        } else {
+            serde_json::from_reader(reader)?
+        };
+        SamplingMaps::from_original(&maps, user_ids)
+    }
+
+    /// Writes the maps with the original user ids to a JSON or pickle file, depending on the extension of the path.
+    pub fn write_to_path(&self, path: &str, user_ids: &UserIds) -> Result<(), Box<dyn Error>> {
+        let maps = self.to_original(user_ids)?;
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        if is_pickle(path) {
+            serde_pickle::to_writer(&mut writer, &maps, Default::default())?;
+        } else {
+            serde_json::to_writer_pretty(writer, &maps)?;
+        }
+        Ok(())
+    }
+
+    /// Checks that every sampled user and trace index exists in the given traces, e.g. that the maps were sampled
+    /// from a dataset parsed with the same parameters. Users are reported by their original ids.
+    pub fn validate<T>(
+        &self,
+        user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+        user_ids: &UserIds,
+    ) -> Result<(), Box<dyn Error>> {
+        let maps = [&self.user_to_target_idx_map, &self.user_to_sample_idx_map];
+        let indices = maps
+            .iter()
+            .flat_map(|map| map.iter())
+            .flat_map(|(user, indices)| indices.iter().map(move |idx| (user, idx)))
+            .chain(self.user_to_test_idx_map.iter());
+        for (user, idx) in indices {
+            match user_to_vector_map.get(user) {
+                Some(traces) if *idx < traces.len() => (),
+                Some(_) => {
+                    let user = user_ids.original(*user);
+                    return Err(format!("Error: Trace {:?} of user {:?} does not exist.", idx, user).into());
+                }
+                None => {
+                    let user = user_ids.original(*user);
+                    return Err(format!("Error: User {:?} does not exist after filtering.", user).into());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn into_maps(self) -> AttackMaps {
+        (
+            self.user_to_target_idx_map.into_iter().collect(),
+            self.user_to_sample_idx_map.into_iter().collect(),
+            self.user_to_test_idx_map.into_iter().collect(),
+            self.stratification,
+        )
+    }
+}
+
+/// Returns the users that can be attacked under their split, in ascending order.
+fn attackable_users(user_to_split_map: &BTreeMap<u32, Split>, target_trace_sample_size: usize) -> Vec<u32> {
+    user_to_split_map
+        .iter()
+        .filter(|(_, split)| split.is_attackable(target_trace_sample_size))
+        .map(|(user, _)| *user)
+        .collect()
+}
 
 /// Samples a subset of users and a number of target traces indices.
 ///
 /// The number of sampled users corresponds to the number of linkage attacks that will
-/// be performed during evaluation. The sampled target traces are found in the second half of the
-/// cronologically ordered history of each user.
-pub fn gen_user_to_target_idx_map<R: Rng, T>(
-    user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+/// be performed during evaluation. The sampled target traces are found among the candidate target traces of the
+/// split of each user, by default the second half of the cronologically ordered history. Users that cannot be
+/// attacked under the split are not sampled. With a stratification, the same number of users is sampled from each
+/// stratum instead of sampling users uniformly.
+pub fn gen_user_to_target_idx_map<R: Rng>(
+    user_to_split_map: &BTreeMap<u32, Split>,
+    stratification: Option<&Stratification>,
     rng: &mut R,
     user_sample_size: usize,
     target_trace_sample_size: usize,
-) -> HashMap<u32, Vec<usize>> {
-    let user_list: Vec<u32> = user_to_vector_map.keys().cloned().collect();
-    let sampled_clients_list = user_list.iter().choose_multiple(rng, user_sample_size);
-    let mut user_to_target_idx_map = HashMap::<u32, Vec<usize>>::new();
+) -> BTreeMap<u32, Vec<usize>> {
+    let user_list: Vec<u32> = attackable_users(user_to_split_map, target_trace_sample_size);
+    let sampled_clients_list: Vec<u32> = match stratification {
+        Some(stratification) => stratification.choose_users(&user_list, rng, user_sample_size),
+        None => user_list
+            .iter()
+            .choose_multiple(rng, user_sample_size)
+            .into_iter()
+            .cloned()
+            .collect(),
+    };
+    let mut user_to_target_idx_map = BTreeMap::<u32, Vec<usize>>::new();
     for client in sampled_clients_list.into_iter() {
-        let traces_list = &user_to_vector_map.get(client).unwrap();
-        let trace_len = traces_list.len();
-        // Split history in 50%/50%
-        let split_idx = trace_len / 2;
-        let indices: Vec<usize> = (split_idx..trace_len).collect();
+        let indices: Vec<usize> = user_to_split_map.get(&client).unwrap().targets.clone();
         let sampled_target_idx = indices
             .into_iter()
             .choose_multiple(rng, target_trace_sample_size);
-        user_to_target_idx_map.insert(*client, sampled_target_idx);
+        user_to_target_idx_map.insert(client, sampled_target_idx);
     }
-    return user_to_target_idx_map;
+    user_to_target_idx_map
 }
 
 /// Samples the observed traces for each user and store sample indices in map.
 ///
-/// The sampled trace indices are stored in a tree map because the tree map stores the keys in a fixed order.
-/// The order is important for consistent sampling and reproducability. The sampled observed traces are found in
-/// the first half of the cronologically ordered history of each user. By setting the trace_sample_size to 0, 50%
-/// of the entire history are sampled.
-pub fn gen_user_to_sample_idx_map<R: Rng, T>(
-    user_to_vector_map: &BTreeMap<u32, Vec<T>>,
+/// The splits are stored in a tree map because the tree map stores the keys in a fixed order.
+/// The order is important for consistent sampling and reproducability. The sampled observed traces are found among
+/// the observed traces of the split of each user, by default the first half of the cronologically ordered history.
+/// The sampled target traces of a user are never observed. Users without any observed trace are left out. By
+/// setting the trace_sample_size to 0, all observed traces are sampled.
+pub fn gen_user_to_sample_idx_map<R: Rng>(
+    user_to_split_map: &BTreeMap<u32, Split>,
+    user_to_target_idx_map: &BTreeMap<u32, Vec<usize>>,
     rng: &mut R,
     trace_sample_size: usize,
-) -> HashMap<u32, Vec<usize>> {
-    let mut user_to_sample_idx_map: HashMap<u32, Vec<usize>> = HashMap::new();
-    for (client, traces_list) in user_to_vector_map.into_iter() {
-        let client = client.clone();
-        let trace_len = traces_list.len();
-        let split_idx = trace_len / 2;
-        let indices: Vec<usize> = (0..split_idx).collect();
+) -> BTreeMap<u32, Vec<usize>> {
+    let mut user_to_sample_idx_map: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+    for (client, split) in user_to_split_map.iter() {
+        let client = *client;
+        let target_idx_list = user_to_target_idx_map.get(&client);
+        let indices: Vec<usize> = split
+            .observed
+            .iter()
+            .filter(|idx| target_idx_list.is_none_or(|targets| !targets.contains(idx)))
+            .cloned()
+            .collect();
+        if indices.is_empty() {
+            continue;
+        }
         let sampled_idx = indices.into_iter().choose_multiple(rng, trace_sample_size);
         user_to_sample_idx_map.insert(client, sampled_idx);
     }
@@ -55,15 +335,16 @@ pub fn gen_user_to_sample_idx_map<R: Rng, T>(
 /// Samples a test traces for each user from the observed traces.
 ///
 /// When conducting the linkage attack, the target trace can be compared to all observed traces
-/// or to a randomly selected trace out of the observed traces, called the test trace.
+/// or to a randomly selected trace out of the observed traces, called the test trace. The users are visited in
+/// ascending order, so that the same seed always yields the same test traces.
 pub fn gen_user_to_test_idx_map<R: Rng>(
-    user_to_sample_idx_map: &HashMap<u32, Vec<usize>>,
+    user_to_sample_idx_map: &BTreeMap<u32, Vec<usize>>,
     rng: &mut R,
-) -> HashMap<u32, usize> {
-    let mut user_to_test_idx_map: HashMap<u32, usize> = HashMap::new();
-    for (client, sample_idx_list) in user_to_sample_idx_map.into_iter() {
+) -> BTreeMap<u32, usize> {
+    let mut user_to_test_idx_map: BTreeMap<u32, usize> = BTreeMap::new();
+    for (client, sample_idx_list) in user_to_sample_idx_map.iter() {
         let test_idx = sample_idx_list.choose(rng).unwrap();
-        user_to_test_idx_map.insert(client.clone(), test_idx.clone());
+        user_to_test_idx_map.insert(*client, *test_idx);
     }
     user_to_test_idx_map
 }
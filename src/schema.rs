@@ -0,0 +1,401 @@
+use chrono::{prelude::DateTime, Datelike, Timelike, Utc};
+use chrono_tz::Tz;
+use crate::binning::Binning;
+use crate::osm::RoadProperty;
+use crate::spatial;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    error::Error,
+    fs::File,
+    io::BufReader,
+    str::FromStr,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Declarative description of the columns of the input data set.
+///
+/// The schema names the columns holding the user identifier and the timestamp and maps an arbitrary
+/// list of further columns to attributes. Every attribute can be selected as data field for the analysis.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schema {
+    pub user_id: String,
+    pub timestamp: String,
+    #[serde(default)]
+    pub coordinates: Option<Coordinates>,
+    /// IANA name of the time zone in which timestamp attributes are extracted. Defaults to UTC.
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Column holding the IANA time zone of each row, which takes precedence over the time zone of the dataset
+    #[serde(default)]
+    pub timezone_column: Option<String>,
+    pub attributes: Vec<Attribute>,
+}
+
+/// Names of the columns holding the raw GPS coordinates in decimal degrees.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coordinates {
+    pub lat: String,
+    pub lon: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attribute {
+    pub name: String,
+    #[serde(default)]
+    column: Option<String>,
+    #[serde(flatten)]
+    pub kind: AttributeKind,
+    #[serde(default)]
+    pub comparison: Comparison,
+    /// Binning of the values of a numeric attribute. Without binning, each distinct value forms its own category.
+    #[serde(default)]
+    pub binning: Option<Binning>,
+    #[serde(default)]
+    missing: MissingPolicy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AttributeKind {
+    Categorical,
+    Numeric,
+    Timestamp {
+        #[serde(
+            serialize_with = "serialize_time_unit",
+            deserialize_with = "deserialize_time_unit"
+        )]
+        unit: &'static TimeUnit,
+    },
+    Road {
+        property: RoadProperty,
+    },
+    Geohash {
+        precision: usize,
+    },
+    HexGrid {
+        size: f64,
+    },
+}
+
+/// A unit that is derived from a unix timestamp, e.g. the hour of the day.
+#[derive(Debug)]
+pub struct TimeUnit {
+    pub name: &'static str,
+    /// Number of distinct values the unit can take
+    pub num_bins: usize,
+    /// Whether the unit takes a single value per trace instead of one value per point
+    pub trace_level: bool,
+    extract: fn(DateTime<Tz>) -> u32,
+}
+
+/// Registry of all units that can be derived from a timestamp column.
+pub const TIME_UNITS: &[TimeUnit] = &[
+    TimeUnit {
+        name: "hour",
+        num_bins: 24,
+        trace_level: false,
+        extract: |datetime| datetime.hour(),
+    },
+    TimeUnit {
+        name: "day",
+        num_bins: 7,
+        trace_level: true,
+        extract: |datetime| datetime.weekday().num_days_from_monday(),
+    },
+];
+
+/// Specifies how the values of an attribute are compared in the sequence alignment-based approach.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Default)]
+pub enum Comparison {
+    #[default]
+    Alignment,
+    Similarity,
+}
+
+
+/// Specifies how missing (empty) values of an attribute are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+#[derive(Default)]
+pub enum MissingPolicy {
+    /// Missing values form a category of their own
+    #[default]
+    Category,
+    /// Points with a missing value are dropped
+    Skip,
+    /// Missing values match any value during alignment and are left out of histograms
+    Wildcard,
+    /// Missing values match no value, not even another missing value, during alignment and are left out of histograms
+    NonMatch,
+}
+
+
+/// All missing-value policies in the order in which they are reported.
+pub const MISSING_POLICIES: &[MissingPolicy] = &[
+    MissingPolicy::Category,
+    MissingPolicy::Skip,
+    MissingPolicy::Wildcard,
+    MissingPolicy::NonMatch,
+];
+
+impl FromStr for MissingPolicy {
+    type Err = std::string::ParseError;
+    fn from_str(s: &str) -> Result<MissingPolicy, Self::Err> {
+        match s {
+            "category" => Ok(MissingPolicy::Category),
+            "skip" => Ok(MissingPolicy::Skip),
+            "wildcard" => Ok(MissingPolicy::Wildcard),
+            "non_match" => Ok(MissingPolicy::NonMatch),
+            x => panic!("The supplied missing-value policy does not exist: {:?}", x),
+        }
+    }
+}
+
+impl TimeUnit {
+    /// Looks up the unit with the given name in the registry.
+    pub fn from_name(name: &str) -> Option<&'static TimeUnit> {
+        TIME_UNITS.iter().find(|unit| unit.name == name)
+    }
+
+    /// Extracts the value of the unit from a unix timestamp in the local time of the given time zone.
+    pub fn extract(&self, timestamp: f64, timezone: Tz) -> u32 {
+        let date = UNIX_EPOCH + Duration::from_secs_f64(timestamp);
+        (self.extract)(DateTime::<Utc>::from(date).with_timezone(&timezone))
+    }
+}
+
+impl PartialEq for TimeUnit {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+fn serialize_time_unit<S>(unit: &&'static TimeUnit, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(unit.name)
+}
+
+fn deserialize_time_unit<'de, D>(deserializer: D) -> Result<&'static TimeUnit, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    TimeUnit::from_name(&name)
+        .ok_or_else(|| de::Error::custom(format!("unknown time unit {:?}", name)))
+}
+
+/// Looks up the time zone with the given IANA name, e.g. "Europe/Berlin".
+pub fn parse_timezone(name: &str) -> Result<Tz, Box<dyn Error>> {
+    name.trim()
+        .parse::<Tz>()
+        .map_err(|_| format!("Error: Unknown time zone {:?}.", name).into())
+}
+
+impl Attribute {
+    fn new(name: &str, kind: AttributeKind, comparison: Comparison) -> Attribute {
+        Attribute {
+            name: name.to_string(),
+            column: None,
+            kind: kind,
+            comparison: comparison,
+            binning: None,
+            missing: MissingPolicy::Category,
+        }
+    }
+
+    /// Returns the name of the column the attribute is read from. Defaults to the name of the attribute.
+    ///
+    /// Spatial attributes are not read from a column but derived from the coordinates.
+    pub fn column(&self) -> &str {
+        self.column.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Returns true if the attribute is derived from the coordinates instead of being read from a column.
+    pub fn is_spatial(&self) -> bool {
+        matches!(
+            self.kind,
+            AttributeKind::Road { .. } | AttributeKind::Geohash { .. } | AttributeKind::HexGrid { .. }
+        )
+    }
+
+    /// Returns the policy for missing values. Timestamp attributes never miss values.
+    pub fn missing_policy(&self) -> MissingPolicy {
+        match &self.kind {
+            AttributeKind::Timestamp { .. } => MissingPolicy::Category,
+            _ => self.missing,
+        }
+    }
+
+    /// Returns true if the attribute takes a single value per trace instead of one value per point.
+    pub fn is_trace_level(&self) -> bool {
+        match &self.kind {
+            AttributeKind::Timestamp { unit } => unit.trace_level,
+            _ => false,
+        }
+    }
+}
+
+impl Schema {
+    /// Loads the schema from a JSON file.
+    pub fn from_path(path: &str) -> Result<Schema, Box<dyn Error>> {
+        let file = File::open(path)?;
+        let schema: Schema = serde_json::from_reader(BufReader::new(file))?;
+        if schema.attributes.is_empty() {
+            return Err("Error: The schema does not define any attributes.".into());
+        }
+        if schema.coordinates.is_none() && schema.attributes.iter().any(|attr| attr.is_spatial()) {
+            return Err("Error: Road and grid attributes require the coordinate columns to be specified.".into());
+        }
+        if let Some(timezone) = &schema.timezone {
+            parse_timezone(timezone)?;
+        }
+        for attr in schema.attributes.iter() {
+            if let Some(binning) = &attr.binning {
+                if attr.kind != AttributeKind::Numeric {
+                    return Err(format!("Error: Binning requires attribute {:?} to be numeric.", attr.name).into());
+                }
+                binning.validate()?;
+            }
+            match attr.kind {
+                AttributeKind::Geohash { precision }
+                    if precision == 0 || precision > spatial::MAX_GEOHASH_PRECISION =>
+                {
+                    return Err(format!("Error: Invalid geohash precision for attribute {:?}.", attr.name).into());
+                }
+                AttributeKind::HexGrid { size } if size <= 0. => {
+                    return Err(format!("Error: Invalid hexagon size for attribute {:?}.", attr.name).into());
+                }
+                _ => (),
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Returns the time zone of the dataset.
+    pub fn timezone(&self) -> Tz {
+        match &self.timezone {
+            Some(timezone) => parse_timezone(timezone).unwrap(),
+            None => Tz::UTC,
+        }
+    }
+
+    /// Sets the binning of the numeric attribute with the given name.
+    pub fn set_binning(&mut self, name: &str, binning: Binning) -> Result<(), Box<dyn Error>> {
+        let idx = self
+            .attribute_idx(name)
+            .ok_or_else(|| format!("Error: Wrong data field supplied: {:?}", name))?;
+        let attr = &mut self.attributes[idx];
+        if attr.kind != AttributeKind::Numeric {
+            return Err(format!("Error: Binning requires attribute {:?} to be numeric.", attr.name).into());
+        }
+        attr.binning = Some(binning);
+        Ok(())
+    }
+
+    /// Sets the missing-value policy of the attribute with the given name.
+    pub fn set_missing_policy(&mut self, name: &str, policy: MissingPolicy) -> Result<(), Box<dyn Error>> {
+        let idx = self
+            .attribute_idx(name)
+            .ok_or_else(|| format!("Error: Wrong data field supplied: {:?}", name))?;
+        self.attributes[idx].missing = policy;
+        Ok(())
+    }
+
+    /// Returns the index of the attribute with the given name.
+    pub fn attribute_idx(&self, name: &str) -> Option<usize> {
+        self.attributes.iter().position(|attr| attr.name == name)
+    }
+
+    /// Returns the names of the attributes with the given indices.
+    pub fn attribute_names(&self, indices: &[usize]) -> Vec<String> {
+        indices
+            .iter()
+            .map(|idx| self.attributes[*idx].name.clone())
+            .collect()
+    }
+}
+
+impl Default for Schema {
+    /// The schema of the original pre-geocoded mobility data set.
+    fn default() -> Self {
+        let hour = AttributeKind::Timestamp {
+            unit: TimeUnit::from_name("hour").unwrap(),
+        };
+        let day = AttributeKind::Timestamp {
+            unit: TimeUnit::from_name("day").unwrap(),
+        };
+        let mut attributes = vec![
+            Attribute::new("speed", AttributeKind::Numeric, Comparison::Alignment),
+            Attribute::new("heading", AttributeKind::Numeric, Comparison::Alignment),
+            Attribute::new("street", AttributeKind::Categorical, Comparison::Alignment),
+            Attribute::new("postcode", AttributeKind::Categorical, Comparison::Similarity),
+            Attribute::new("state", AttributeKind::Categorical, Comparison::Similarity),
+            Attribute::new("highway", AttributeKind::Categorical, Comparison::Alignment),
+            Attribute::new("hamlet", AttributeKind::Categorical, Comparison::Alignment),
+            Attribute::new("suburb", AttributeKind::Categorical, Comparison::Alignment),
+            Attribute::new("village", AttributeKind::Categorical, Comparison::Alignment),
+            Attribute::new("location_code", AttributeKind::Categorical, Comparison::Alignment),
+            Attribute::new("hour", hour, Comparison::Alignment),
+            Attribute::new("day", day, Comparison::Similarity),
+        ];
+        for attr in attributes.iter_mut() {
+            if let AttributeKind::Timestamp { .. } = attr.kind {
+                attr.column = Some("timestamp".to_string());
+            }
+        }
+        Schema {
+            user_id: "user_id".to_string(),
+            timestamp: "timestamp".to_string(),
+            coordinates: None,
+            timezone: None,
+            timezone_column: None,
+            attributes: attributes,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn berlin() -> Tz {
+        parse_timezone("Europe/Berlin").unwrap()
+    }
+
+    #[test]
+    fn extracts_hour_across_dst_transition() {
+        let hour = TimeUnit::from_name("hour").unwrap();
+        // Clocks jump from 02:00 CET to 03:00 CEST at 01:00 UTC on 2021-03-28
+        assert_eq!(hour.extract(1616893199., berlin()), 1);
+        assert_eq!(hour.extract(1616893200., berlin()), 3);
+        assert_eq!(hour.extract(1616893200., Tz::UTC), 1);
+    }
+
+    #[test]
+    fn extracts_ambiguous_local_time() {
+        let hour = TimeUnit::from_name("hour").unwrap();
+        // 02:30 occurs twice on 2021-10-31, once in CEST and an hour later in CET
+        assert_eq!(hour.extract(1635640200., berlin()), 2);
+        assert_eq!(hour.extract(1635643800., berlin()), 2);
+        assert_eq!(hour.extract(1635643800., Tz::UTC), 1);
+    }
+
+    #[test]
+    fn extracts_day_in_local_time() {
+        let day = TimeUnit::from_name("day").unwrap();
+        // 23:30 UTC on Sunday is already Monday in Berlin
+        assert_eq!(day.extract(1609716600., Tz::UTC), 6);
+        assert_eq!(day.extract(1609716600., berlin()), 0);
+    }
+
+    #[test]
+    fn parses_time_zones() {
+        assert_eq!(parse_timezone(" Europe/Berlin ").unwrap(), Tz::Europe__Berlin);
+        assert!(parse_timezone("Europe/Atlantis").is_err());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::cli::Config;
+use crate::parse::Record;
+use crate::spatial;
+
+use std::{error::Error, str::FromStr};
+
+/// Rules to split the history of a user into separate mobility traces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SegmentationStrategy {
+    /// A new trace starts if the time gap between two points reaches the delay limit
+    Gap,
+    /// A new trace starts once the vehicle leaves a place where it stayed within a radius for a minimum duration
+    StayPoint,
+    /// A new trace starts once the vehicle moves again after standing still (or reporting nothing) for a minimum
+    /// duration, e.g. because the ignition was turned off
+    SpeedZero,
+    /// A new trace starts with every time window of fixed length
+    FixedWindow,
+}
+
+impl FromStr for SegmentationStrategy {
+    type Err = std::string::ParseError;
+    fn from_str(s: &str) -> Result<SegmentationStrategy, Self::Err> {
+        match s {
+            "gap" => Ok(SegmentationStrategy::Gap),
+            "stay_point" => Ok(SegmentationStrategy::StayPoint),
+            "speed_zero" => Ok(SegmentationStrategy::SpeedZero),
+            "fixed_window" => Ok(SegmentationStrategy::FixedWindow),
+            x => panic!("The supplied segmentation strategy does not exist: {:?}", x),
+        }
+    }
+}
+
+/// Decides where the traces of a user begin while the points of the user are visited in chronological order.
+#[derive(Debug)]
+pub struct Segmenter {
+    strategy: SegmentationStrategy,
+    delay_limit: f64,
+    stay_radius: f64,
+    stay_duration: f64,
+    stop_duration: f64,
+    window: f64,
+    /// Attribute holding the speed, only required for the speed-zero strategy
+    speed_field: Option<usize>,
+    prev_time: Option<f64>,
+    /// Time and coordinates of the first point of the current stay candidate
+    anchor: Option<(f64, (f64, f64))>,
+    /// Time of the first point since which the vehicle stands still
+    stop_start: Option<f64>,
+}
+
+impl Segmenter {
+    pub fn new(config: &Config) -> Result<Segmenter, Box<dyn Error>> {
+        let strategy = SegmentationStrategy::from_str(&config.segmentation).unwrap();
+        let speed_field = match strategy {
+            SegmentationStrategy::SpeedZero => Some(
+                config
+                    .schema
+                    .attribute_idx(&config.speed_field)
+                    .ok_or_else(|| {
+                        format!("Error: Speed attribute {:?} not found in schema.", config.speed_field)
+                    })?,
+            ),
+            _ => None,
+        };
+        if strategy == SegmentationStrategy::StayPoint && config.schema.coordinates.is_none() {
+            return Err("Error: Stay-point segmentation requires the coordinates of the schema.".into());
+        }
+        let params: &[(&str, f64)] = match strategy {
+            SegmentationStrategy::Gap => &[],
+            SegmentationStrategy::FixedWindow => &[("window", config.window)],
+            SegmentationStrategy::StayPoint => {
+                &[("stay_radius", config.stay_radius), ("stay_duration", config.stay_duration)]
+            }
+            SegmentationStrategy::SpeedZero => &[("stop_duration", config.stop_duration)],
+        };
+        for (name, value) in params.iter() {
+            if value.is_nan() || *value <= 0. {
+                return Err(format!("Error: --{} must be positive, got {:?}.", name, value).into());
+            }
+        }
+        Ok(Segmenter {
+            strategy: strategy,
+            delay_limit: config.delay_limit,
+            stay_radius: config.stay_radius,
+            stay_duration: config.stay_duration,
+            stop_duration: config.stop_duration,
+            window: config.window,
+            speed_field: speed_field,
+            prev_time: None,
+            anchor: None,
+            stop_start: None,
+        })
+    }
+
+    /// Returns the attribute that needs to be read in addition to the selected fields, if any.
+    pub fn speed_field(&self) -> Option<usize> {
+        self.speed_field
+    }
+
+    /// Returns true if the coordinates of each point need to be read.
+    pub fn needs_coordinates(&self) -> bool {
+        self.strategy == SegmentationStrategy::StayPoint
+    }
+
+    /// Resets the state before the points of the next user are visited.
+    pub fn reset(&mut self) {
+        self.prev_time = None;
+        self.anchor = None;
+        self.stop_start = None;
+    }
+
+    /// Returns true if the given point starts a new trace. Must be called for every point of the user in order.
+    pub fn is_trace_start(&mut self, record: &Record) -> bool {
+        let time = record.timestamp;
+        let is_trace_start = match self.prev_time {
+            None => false,
+            Some(prev_time) => match self.strategy {
+                SegmentationStrategy::Gap => time - prev_time >= self.delay_limit,
+                SegmentationStrategy::FixedWindow => {
+                    (time / self.window).floor() != (prev_time / self.window).floor()
+                }
+                SegmentationStrategy::StayPoint => self.leaves_stay_point(time, record.coordinates.unwrap()),
+                SegmentationStrategy::SpeedZero => {
+                    let speed = record.values[self.speed_field.unwrap()].trim().parse::<f64>().ok();
+                    self.moves_after_stop(time, prev_time, speed)
+                }
+            },
+        };
+        if self.prev_time.is_none() && self.strategy == SegmentationStrategy::StayPoint {
+            self.anchor = Some((time, record.coordinates.unwrap()));
+        }
+        self.prev_time = Some(time);
+        is_trace_start
+    }
+
+    /// Tracks the current stay candidate and returns true if the point leaves a stay point.
+    fn leaves_stay_point(&mut self, time: f64, coordinates: (f64, f64)) -> bool {
+        let (anchor_time, anchor_coordinates) = self.anchor.unwrap();
+        if spatial::haversine_dist(anchor_coordinates, coordinates) <= self.stay_radius {
+            return false;
+        }
+        // The previous point is the last one within the radius of the stay candidate. A gap without any points counts
+        // as a stay, like in `moves_after_stop`, since vehicles usually stop reporting while parked.
+        let prev_time = self.prev_time.unwrap();
+        let has_stayed = prev_time - anchor_time >= self.stay_duration || time - prev_time >= self.stay_duration;
+        self.anchor = Some((time, coordinates));
+        has_stayed
+    }
+
+    /// Tracks standstills and returns true if the vehicle moves again after a long enough stop.
+    ///
+    /// A gap without any points counts as standstill since vehicles usually stop reporting when the ignition is off.
+    /// Points without a valid speed neither start nor end a standstill.
+    fn moves_after_stop(&mut self, time: f64, prev_time: f64, speed: Option<f64>) -> bool {
+        let has_gap = time - prev_time >= self.stop_duration;
+        match speed {
+            Some(speed) if speed <= 0.0 => {
+                if self.stop_start.is_none() {
+                    self.stop_start = Some(if has_gap { prev_time } else { time });
+                }
+                false
+            }
+            Some(_) => {
+                let has_stopped = self
+                    .stop_start
+                    .is_some_and(|stop_start| time - stop_start >= self.stop_duration);
+                self.stop_start = None;
+                has_stopped || has_gap
+            }
+            None => {
+                if has_gap {
+                    self.stop_start = None;
+                }
+                has_gap
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stay_point_segmenter() -> Segmenter {
+        Segmenter {
+            strategy: SegmentationStrategy::StayPoint,
+            delay_limit: 1800.,
+            stay_radius: 100.,
+            stay_duration: 600.,
+            stop_duration: 300.,
+            window: 3600.,
+            speed_field: None,
+            prev_time: None,
+            anchor: None,
+            stop_start: None,
+        }
+    }
+
+    /// Returns a point the given number of meters north of the reference point.
+    fn record(timestamp: f64, meters_north: f64) -> Record {
+        let lat = 48.1 + (meters_north / spatial::EARTH_RADIUS).to_degrees();
+        Record {
+            user_id: "a".to_string(),
+            timestamp: timestamp,
+            coordinates: Some((lat, 11.5)),
+            values: Vec::new(),
+        }
+    }
+
+    /// Returns the indices of the points that start a new trace.
+    fn trace_starts(segmenter: &mut Segmenter, records: &[Record]) -> Vec<usize> {
+        records
+            .iter()
+            .enumerate()
+            .filter(|(_, record)| segmenter.is_trace_start(record))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    #[test]
+    fn splits_after_stay() {
+        // Drive, park for 15 minutes while reporting, then drive on
+        let records = [
+            record(0., 0.),
+            record(60., 20.),
+            record(600., 30.),
+            record(960., 10.),
+            record(1020., 500.),
+            record(1080., 1000.),
+        ];
+        assert_eq!(trace_starts(&mut stay_point_segmenter(), &records), [4]);
+    }
+
+    #[test]
+    fn splits_after_gap() {
+        // Park and stop reporting, then come back outside of the radius hours later
+        let records = [record(0., 0.), record(60., 500.), record(7260., 5000.), record(7320., 5500.)];
+        assert_eq!(trace_starts(&mut stay_point_segmenter(), &records), [2]);
+    }
+
+    #[test]
+    fn does_not_split_when_passing_through() {
+        // Keep reporting while driving through, never staying within the radius for long
+        let records: Vec<Record> = (0..20).map(|idx| record(idx as f64 * 60., idx as f64 * 300.)).collect();
+        assert!(trace_starts(&mut stay_point_segmenter(), &records).is_empty());
+    }
+}
@@ -1,5 +1,6 @@
-use crate::parse::DataFields;
+use crate::schema::{Comparison, MissingPolicy, Schema};
 use crate::sequence::trace::{reverse_trace, SeqTrace};
+use crate::trace::MISSING;
 use crate::utils;
 use crate::{cli, sequence};
 
@@ -13,6 +14,7 @@ use std::{
 
 /// Runs the evaluation by conducting a specified number of linkage attacks that are
 /// independent from each other. The traces are compared using the sequence alignment-based approach.
+/// Returns the accuracy scores.
 /// 
 /// Due to the independence, the linkage attacks can be performed in parallel. 
 pub fn eval(
@@ -21,80 +23,42 @@ pub fn eval(
     user_to_target_idx_map: &HashMap<u32, Vec<usize>>,
     user_to_sample_idx_map: &HashMap<u32, Vec<usize>>,
     user_to_test_idx_map: &HashMap<u32, usize>,
-) {
-    let result_list: Vec<(bool, bool, bool)> = user_to_target_idx_map
+) -> utils::Scores {
+    let result_list: Vec<utils::Attack> = user_to_target_idx_map
         .par_iter()
         .map(|(user_target, target_idx_list)| {
             eval_step(
                 config,
                 user_target,
-                &target_idx_list,
-                &user_to_seq_map,
-                &user_to_sample_idx_map,
-                &user_to_test_idx_map,
+                target_idx_list,
+                user_to_seq_map,
+                user_to_sample_idx_map,
+                user_to_test_idx_map,
             )
         })
         .collect();
 
-    let mut top_1_list: Vec<f64> = Vec::with_capacity(result_list.len());
-    let mut top_10_list: Vec<f64> = Vec::with_capacity(result_list.len());
-    let mut top_10_percent_list: Vec<f64> = Vec::with_capacity(result_list.len());
-    for (in_top_1, in_top_10, in_top_10_percent) in result_list.iter() {
-        if *in_top_1 {
-            top_1_list.push(1.0);
-        } else {
-            top_1_list.push(0.0);
-        }
-        if *in_top_10 {
-            top_10_list.push(1.0);
-        } else {
-            top_10_list.push(0.0);
-        }
-        if *in_top_10_percent {
-            top_10_percent_list.push(1.0);
-        } else {
-            top_10_percent_list.push(0.0);
-        }
-    }
-
-    let top_1: f64 = utils::mean(&top_1_list);
-    log::info!("Rank 1: {:?}", top_1);
-    let top_10: f64 = utils::mean(&top_10_list);
-    log::info!("Top 10: {:?}", top_10);
-    let top_10_percent: f64 = utils::mean(&top_10_percent_list);
-    log::info!("Top 10 Percent: {:?}", top_10_percent);
-
-    let top_1_std = utils::std_deviation(&top_1_list);
-    let top_10_std = utils::std_deviation(&top_10_list);
-    let top_10_percent_std = utils::std_deviation(&top_10_percent_list);
-
-    // Write metrics to final evaluation file
-    utils::write_to_file(
-        config,
-        top_1,
-        top_1_std,
-        top_10,
-        top_10_std,
-        top_10_percent,
-        top_10_percent_std,
-    )
-    .expect("Error writing to evaluation file.");
+    let scores = utils::Scores::new(config, &result_list, true);
+    log::info!("Rank 1: {:?}", scores.top_1);
+    log::info!("Top 10: {:?}", scores.top_10);
+    log::info!("Top 10 Percent: {:?}", scores.top_10_percent);
+    scores
 }
 
 /// Performs a single independent linkage attack.
 fn eval_step(
     config: &cli::Config,
     user_target: &u32,
-    target_idx_list: &Vec<usize>,
+    target_idx_list: &[usize],
     user_to_seq_map: &BTreeMap<u32, Vec<SeqTrace>>,
     user_to_sample_idx_map: &HashMap<u32, Vec<usize>>,
     user_to_test_idx_map: &HashMap<u32, usize>,
-) -> (bool, bool, bool) {
+) -> utils::Attack {
     let mut result_map: HashMap<u32, OrderedFloat<f64>> = HashMap::new();
     let mut result_tuples: Vec<(u32, OrderedFloat<f64>)> =
         Vec::with_capacity(user_to_seq_map.len());
 
-    for target_idx in target_idx_list.into_iter() {
+    for target_idx in target_idx_list.iter() {
         let target_trace = user_to_seq_map
             .get(user_target)
             .unwrap()
@@ -102,10 +66,14 @@ fn eval_step(
             .unwrap();
         let reverse_target_trace = reverse_trace(target_trace);
 
-        for (user, traces) in user_to_seq_map.into_iter() {
-            let samples_idx = user_to_sample_idx_map.get(user).unwrap();
+        for (user, traces) in user_to_seq_map.iter() {
+            // Users without observed traces, e.g. withheld in the open-world mode, are no candidates
+            let samples_idx = match user_to_sample_idx_map.get(user) {
+                Some(samples_idx) => samples_idx,
+                None => continue,
+            };
             let sampled_traces: Vec<SeqTrace> = samples_idx
-                .into_iter()
+                .iter()
                 .map(|idx| traces.get(*idx).unwrap().clone())
                 .collect();
 
@@ -114,16 +82,18 @@ fn eval_step(
                     sequence::trace::gen_typical_trace(&sampled_traces);
 
                 let mut score = compute_alignment_scores(
+                    &config.schema,
                     &config.fields,
                     &config.strategy,
                     &config.scope,
                     &config.scoring_matrix,
-                    &target_trace,
+                    target_trace,
                     &typical_ref_trace,
                 );
 
                 if config.reverse {
                     let score_reverse = compute_alignment_scores(
+                        &config.schema,
                         &config.fields,
                         &config.strategy,
                         &config.scope,
@@ -135,20 +105,22 @@ fn eval_step(
                         score = score_reverse;
                     }
                 }
-                result_tuples.push((user.clone(), OrderedFloat(score)));
+                result_tuples.push((*user, OrderedFloat(score)));
             } else if !config.typical && !config.dependent {
                 for ref_trace in sampled_traces.into_iter() {
                     let mut score = compute_alignment_scores(
+                        &config.schema,
                         &config.fields,
                         &config.strategy,
                         &config.scope,
                         &config.scoring_matrix,
-                        &target_trace,
+                        target_trace,
                         &ref_trace,
                     );
 
                     if config.reverse {
                         let score_reverse = compute_alignment_scores(
+                            &config.schema,
                             &config.fields,
                             &config.strategy,
                             &config.scope,
@@ -160,22 +132,23 @@ fn eval_step(
                             score = score_reverse;
                         }
                     }
-                    result_tuples.push((user.clone(), OrderedFloat(score)));
+                    result_tuples.push((*user, OrderedFloat(score)));
                 }
             } else {
-                let test_idx: usize = user_to_test_idx_map.get(user).unwrap().clone();
+                let test_idx: usize = *user_to_test_idx_map.get(user).unwrap();
                 let ref_trace: SeqTrace =
                     traces.get(test_idx).unwrap().clone();
                 let score = compute_alignment_scores(
+                    &config.schema,
                     &config.fields,
                     &config.strategy,
                     &config.scope,
                     &config.scoring_matrix,
-                    &target_trace,
+                    target_trace,
                     &ref_trace,
                 );
                 *result_map
-                    .entry(user.clone())
+                    .entry(*user)
                     .or_insert(OrderedFloat(0.0)) += OrderedFloat(score);
             }
         }
@@ -184,16 +157,19 @@ fn eval_step(
         result_tuples = result_map.into_iter().collect();
     }
     result_tuples.sort_unstable_by_key(|k| Reverse(k.1));
-    let cutoff: usize = (0.1 * user_to_seq_map.len() as f64) as usize;
-    let is_top_10_percent = utils::is_target_in_top_k(user_target, &result_tuples[..cutoff]);
-    let is_top_10: bool = utils::is_target_in_top_k(user_target, &result_tuples[..10]);
-    let is_top_1 = user_target.clone() == result_tuples[0].0;
-    (is_top_1, is_top_10, is_top_10_percent)
+    utils::Attack::new(
+        config,
+        user_target,
+        user_to_sample_idx_map.contains_key(user_target),
+        &result_tuples,
+        user_to_sample_idx_map.len(),
+    )
 }
 
 /// Calculates the alignment score between the target and the reference trace.
 fn compute_alignment_scores(
-    fields: &Vec<DataFields>,
+    schema: &Schema,
+    fields: &[usize],
     strategy: &str,
     scope: &str,
     scoring_matrix: &[isize],
@@ -203,98 +179,31 @@ fn compute_alignment_scores(
     let mut align_scores = Vec::<f64>::with_capacity(fields.len());
     let mut unnormalized_align_scores = Vec::<f64>::with_capacity(fields.len());
 
-    for field in fields.into_iter() {
-        let score = match field {
-            DataFields::Speed => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.speed.clone(),
-                ref_trace.speed.clone(),
-            ),
-            DataFields::Heading => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.heading.clone(),
-                ref_trace.heading.clone(),
-            ),
-            DataFields::Street => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.street.clone(),
-                ref_trace.street.clone(),
-            ),
-            DataFields::Postcode => compute_similarity_score(
-                target_trace.postcode.clone(),
-                ref_trace.postcode.clone(),
-            ),
-            DataFields::State => compute_similarity_score(
-                target_trace.state.clone(),
-                ref_trace.state.clone(),
-            ),
-            DataFields::Day => compute_similarity_score(
-                target_trace.day.clone(),
-                ref_trace.day.clone(),
-            ),
-            DataFields::Hour => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.hour.clone(),
-                ref_trace.hour.clone(),
-            ),
-            DataFields::Highway => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.highway.clone(),
-                ref_trace.highway.clone(),
-            ),
-            DataFields::Hamlet => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.hamlet.clone(),
-                ref_trace.hamlet.clone(),
-            ),
-            DataFields::Suburb => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.suburb.clone(),
-                ref_trace.suburb.clone(),
-            ),
-            DataFields::Village => compute_sequence_alignment(
-                strategy,
-                scope,
-                scoring_matrix,
-                target_trace.village.clone(),
-                ref_trace.village.clone(),
-            ),
-            DataFields::LocationCode => compute_sequence_alignment(
+    for field in fields.iter() {
+        let attr = &schema.attributes[*field];
+        let target_sequence = target_trace.columns[*field].clone();
+        let ref_sequence = ref_trace.columns[*field].clone();
+
+        let missing = attr.missing_policy();
+        let score = match attr.comparison {
+            Comparison::Alignment => compute_sequence_alignment(
                 strategy,
                 scope,
                 scoring_matrix,
-                target_trace.location_code.clone(),
-                ref_trace.location_code.clone(),
+                missing,
+                target_sequence,
+                ref_sequence,
             ),
+            Comparison::Similarity => {
+                compute_similarity_score(missing, target_sequence, ref_sequence)
+            }
         };
 
-        match field {
-            DataFields::Speed => unnormalized_align_scores.push(score),
-            DataFields::Heading => unnormalized_align_scores.push(score),
-            DataFields::Street => unnormalized_align_scores.push(score),
-            DataFields::Postcode => unnormalized_align_scores.push(score),
-            DataFields::State => unnormalized_align_scores.push(score),
-            DataFields::Day => align_scores.push(score),
-            DataFields::Hour => unnormalized_align_scores.push(score),
-            DataFields::Highway => unnormalized_align_scores.push(score),
-            DataFields::Hamlet => unnormalized_align_scores.push(score),
-            DataFields::Suburb => unnormalized_align_scores.push(score),
-            DataFields::Village => unnormalized_align_scores.push(score),
-            DataFields::LocationCode => unnormalized_align_scores.push(score),
+        // Scores of attributes on trace level are already within [0, 1]
+        if attr.is_trace_level() {
+            align_scores.push(score);
+        } else {
+            unnormalized_align_scores.push(score);
         }
     }
 
@@ -307,10 +216,20 @@ fn compute_alignment_scores(
     avg_score
 }
 
+/// Returns true if two values match with respect to the policy for missing values.
+fn is_match(missing: MissingPolicy, target_val: u32, ref_val: u32) -> bool {
+    match missing {
+        MissingPolicy::Wildcard => target_val == MISSING || ref_val == MISSING || target_val == ref_val,
+        MissingPolicy::NonMatch => target_val != MISSING && target_val == ref_val,
+        MissingPolicy::Category | MissingPolicy::Skip => target_val == ref_val,
+    }
+}
+
 fn compute_sequence_alignment(
     strategy: &str,
     scope: &str,
     scoring_matrix: &[isize],
+    missing: MissingPolicy,
     target_trace: Vec<u32>,
     ref_trace: Vec<u32>,
 ) -> f64 {
@@ -323,7 +242,7 @@ fn compute_sequence_alignment(
                 scoring_matrix[3],
             );
             AlignmentSet::new(target_trace.len(), ref_trace.len(), strategy, |x, y| {
-                target_trace[x] == ref_trace[y]
+                is_match(missing, target_trace[x], ref_trace[y])
             })
             .unwrap()
         }
@@ -335,7 +254,7 @@ fn compute_sequence_alignment(
                 scoring_matrix[3],
             );
             AlignmentSet::new(target_trace.len(), ref_trace.len(), strategy, |x, y| {
-                target_trace[x] == ref_trace[y]
+                is_match(missing, target_trace[x], ref_trace[y])
             })
             .unwrap()
         }
@@ -350,12 +269,19 @@ fn compute_sequence_alignment(
     score
 }
 
-fn compute_similarity_score<T: std::cmp::PartialEq>(target_trace_val: T, ref_trace_val: T) -> f64 {
-    let score;
-    if target_trace_val == ref_trace_val {
-        score = 1.0;
+fn compute_similarity_score(
+    missing: MissingPolicy,
+    target_trace_val: Vec<u32>,
+    ref_trace_val: Vec<u32>,
+) -> f64 {
+    if target_trace_val.len() == ref_trace_val.len()
+        && target_trace_val
+            .iter()
+            .zip(ref_trace_val.iter())
+            .all(|(target_val, ref_val)| is_match(missing, *target_val, *ref_val))
+    {
+        1.0
     } else {
-        score = 0.0;
+        0.0
     }
-    score
 }
@@ -0,0 +1,282 @@
+use crate::parse::Record;
+
+use csv::{ReaderBuilder, StringRecord, WriterBuilder};
+use ordered_float::OrderedFloat;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    error::Error,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
+
+/// Counter to create unique names for the temporary files of the sorted runs.
+static RUN_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+type RecordResult = Result<Record, Box<dyn Error>>;
+
+/// Sorted chunk of records that has been spilled to a temporary file.
+struct Run {
+    path: PathBuf,
+    reader: csv::Reader<File>,
+    row: StringRecord,
+}
+
+/// Next record of a run, ordered by user, timestamp and run so that the merge is stable.
+struct HeapEntry {
+    record: Record,
+    run_idx: usize,
+}
+
+/// Lazily merges the sorted runs into a single sorted stream of records.
+struct Merge {
+    runs: Vec<Run>,
+    heap: BinaryHeap<Reverse<HeapEntry>>,
+}
+
+/// Sorts the records by user and by timestamp within each user.
+///
+/// Records are collected in memory up to the given buffer size. If the input exceeds the buffer, each full
+/// buffer is sorted and spilled to a temporary file in `dir`. The resulting sorted runs are then merged lazily and
+/// their files are removed once the merge is dropped.
+pub fn sort_records<'a, I>(
+    records: I,
+    buffer_size: usize,
+    dir: &Path,
+) -> Result<Box<dyn Iterator<Item = RecordResult> + 'a>, Box<dyn Error>>
+where
+    I: Iterator<Item = RecordResult>,
+{
+    let mut buffer: Vec<Record> = Vec::new();
+    let mut runs: Vec<Run> = Vec::new();
+    for record in records {
+        buffer.push(record?);
+        if buffer.len() >= buffer_size {
+            runs.push(Run::spill(&mut buffer, dir)?);
+        }
+    }
+
+    if runs.is_empty() {
+        buffer.sort_by(compare_records);
+        return Ok(Box::new(buffer.into_iter().map(Ok)));
+    }
+    if !buffer.is_empty() {
+        runs.push(Run::spill(&mut buffer, dir)?);
+    }
+    log::info!("Merging {:?} sorted runs...", runs.len());
+    Ok(Box::new(Merge::new(runs)?))
+}
+
+fn compare_records(a: &Record, b: &Record) -> Ordering {
+    a.user_id
+        .cmp(&b.user_id)
+        .then(OrderedFloat(a.timestamp).cmp(&OrderedFloat(b.timestamp)))
+}
+
+impl Run {
+    /// Sorts the buffer and writes it to a new temporary file in the directory. The buffer is empty afterwards.
+    fn spill(buffer: &mut Vec<Record>, dir: &Path) -> Result<Run, Box<dyn Error>> {
+        buffer.sort_by(compare_records);
+        let path = dir.join(format!(
+            "linkage-sort-{}-{}.csv",
+            process::id(),
+            RUN_COUNTER.fetch_add(1, AtomicOrdering::SeqCst)
+        ));
+
+        let mut wtr = WriterBuilder::new()
+            .has_headers(false)
+            .from_path(&path)?;
+        let mut row = StringRecord::new();
+        for record in buffer.drain(..) {
+            row.clear();
+            row.push_field(&record.user_id);
+            row.push_field(&record.timestamp.to_string());
+            match record.coordinates {
+                Some((lat, lon)) => {
+                    row.push_field(&lat.to_string());
+                    row.push_field(&lon.to_string());
+                }
+                None => {
+                    row.push_field("");
+                    row.push_field("");
+                }
+            }
+            for value in record.values.iter() {
+                row.push_field(value);
+            }
+            wtr.write_record(&row)?;
+        }
+        wtr.flush()?;
+
+        let reader = ReaderBuilder::new().has_headers(false).from_path(&path)?;
+        Ok(Run {
+            path: path,
+            reader: reader,
+            row: StringRecord::new(),
+        })
+    }
+
+    fn next_record(&mut self) -> Result<Option<Record>, Box<dyn Error>> {
+        if !self.reader.read_record(&mut self.row)? {
+            return Ok(None);
+        }
+        let coordinates = if self.row[2].is_empty() {
+            None
+        } else {
+            Some((self.row[2].parse::<f64>()?, self.row[3].parse::<f64>()?))
+        };
+        Ok(Some(Record {
+            user_id: self.row[0].to_string(),
+            timestamp: self.row[1].parse::<f64>()?,
+            coordinates: coordinates,
+            values: self.row.iter().skip(4).map(String::from).collect(),
+        }))
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for HeapEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_records(&self.record, &other.record).then(self.run_idx.cmp(&other.run_idx))
+    }
+}
+
+impl Merge {
+    fn new(mut runs: Vec<Run>) -> Result<Merge, Box<dyn Error>> {
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (run_idx, run) in runs.iter_mut().enumerate() {
+            if let Some(record) = run.next_record()? {
+                heap.push(Reverse(HeapEntry {
+                    record: record,
+                    run_idx: run_idx,
+                }));
+            }
+        }
+        Ok(Merge {
+            runs: runs,
+            heap: heap,
+        })
+    }
+}
+
+impl Iterator for Merge {
+    type Item = RecordResult;
+
+    fn next(&mut self) -> Option<RecordResult> {
+        let Reverse(entry) = self.heap.pop()?;
+        match self.runs[entry.run_idx].next_record() {
+            Ok(Some(record)) => self.heap.push(Reverse(HeapEntry {
+                record: record,
+                run_idx: entry.run_idx,
+            })),
+            Ok(None) => (),
+            Err(err) => return Some(Err(err)),
+        }
+        Some(Ok(entry.record))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(user_id: &str, timestamp: f64, value: &str) -> Record {
+        Record {
+            user_id: user_id.to_string(),
+            timestamp: timestamp,
+            coordinates: Some((48.1 + timestamp / 1000., 11.5)),
+            values: vec![value.to_string(), String::new()],
+        }
+    }
+
+    /// Returns the records of three users interleaved and in scrambled order of time.
+    fn interleaved_records() -> Vec<Record> {
+        (0..30)
+            .map(|idx| {
+                let user_id = ["c", "a", "b"][idx % 3];
+                let timestamp = ((idx * 7) % 30) as f64;
+                record(user_id, timestamp, &format!("v{}", idx))
+            })
+            .collect()
+    }
+
+    fn spilled_files(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    fn assert_sorted(records: &[Record]) {
+        for pair in records.windows(2) {
+            assert_ne!(compare_records(&pair[0], &pair[1]), Ordering::Greater);
+        }
+    }
+
+    #[test]
+    fn merges_several_spilled_runs() {
+        let mut records = interleaved_records();
+        // Two points of a user at the same time keep the order of the input
+        records.push(record("a", 7., "first"));
+        records.push(record("a", 7., "second"));
+        let expected = records.len();
+
+        let dir = tempfile::tempdir().unwrap();
+        let merge = sort_records(records.into_iter().map(Ok), 4, dir.path()).unwrap();
+        assert_eq!(spilled_files(dir.path()), 8);
+        let sorted: Vec<Record> = merge.collect::<Result<Vec<Record>, Box<dyn Error>>>().unwrap();
+        assert_eq!(sorted.len(), expected);
+        assert_sorted(&sorted);
+        assert_eq!(
+            sorted.iter().map(|record| record.user_id.as_str()).collect::<Vec<&str>>(),
+            [vec!["a"; 12], vec!["b"; 10], vec!["c"; 10]].concat()
+        );
+
+        // Values and coordinates survive the round trip through the temporary files
+        let original = interleaved_records();
+        for record in sorted.iter().filter(|record| record.values[0].starts_with('v')) {
+            let idx: usize = record.values[0][1..].parse().unwrap();
+            assert_eq!(record.user_id, original[idx].user_id);
+            assert_eq!(record.timestamp, original[idx].timestamp);
+            assert_eq!(record.coordinates, original[idx].coordinates);
+            assert_eq!(record.values, original[idx].values);
+        }
+        let ties: Vec<&str> = sorted
+            .iter()
+            .filter(|record| record.user_id == "a" && record.timestamp == 7.)
+            .map(|record| record.values[0].as_str())
+            .collect();
+        assert_eq!(ties, ["v1", "first", "second"]);
+        assert_eq!(spilled_files(dir.path()), 0);
+    }
+
+    #[test]
+    fn sorts_in_memory_below_buffer_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let sorted: Vec<Record> = sort_records(interleaved_records().into_iter().map(Ok), 100, dir.path())
+            .unwrap()
+            .collect::<Result<Vec<Record>, Box<dyn Error>>>()
+            .unwrap();
+        assert_eq!(sorted.len(), 30);
+        assert_sorted(&sorted);
+        assert_eq!(spilled_files(dir.path()), 0);
+    }
+}
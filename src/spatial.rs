@@ -0,0 +1,123 @@
+/// Mean radius of the earth in meters.
+pub const EARTH_RADIUS: f64 = 6_371_000.0;
+
+const GEOHASH_ALPHABET: &[u8] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+
+/// Maximum precision of a geohash, which corresponds to cells of a few centimeters.
+pub const MAX_GEOHASH_PRECISION: usize = 12;
+
+/// Encodes the coordinates as geohash with the given number of characters.
+pub fn geohash(lat: f64, lon: f64, precision: usize) -> String {
+    let mut lat_range = (-90.0, 90.0);
+    let mut lon_range = (-180.0, 180.0);
+    let mut hash = String::with_capacity(precision);
+    let mut is_lon = true;
+    let mut bits = 0;
+    let mut idx = 0;
+
+    while hash.len() < precision {
+        // Alternately bisect the longitude and the latitude range
+        let (range, value) = if is_lon {
+            (&mut lon_range, lon)
+        } else {
+            (&mut lat_range, lat)
+        };
+        let mid = (range.0 + range.1) / 2.0;
+        idx <<= 1;
+        if value >= mid {
+            idx |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        is_lon = !is_lon;
+
+        bits += 1;
+        if bits == 5 {
+            hash.push(GEOHASH_ALPHABET[idx] as char);
+            bits = 0;
+            idx = 0;
+        }
+    }
+    hash
+}
+
+/// Returns the identifier of the hexagonal cell that contains the coordinates.
+///
+/// The coordinates are mapped onto a plane with the equal-area sinusoidal projection, which is tiled with pointy-top
+/// hexagons whose edges are `size` meters long. The cell is identified by its axial coordinates.
+pub fn hex_cell(lat: f64, lon: f64, size: f64) -> String {
+    let x = EARTH_RADIUS * lon.to_radians() * lat.to_radians().cos();
+    let y = EARTH_RADIUS * lat.to_radians();
+
+    // Fractional axial coordinates
+    let q = (3f64.sqrt() / 3.0 * x - y / 3.0) / size;
+    let r = (2.0 / 3.0 * y) / size;
+
+    // Round to the closest hexagon in cube coordinates
+    let s = -q - r;
+    let (mut q_round, mut r_round, s_round) = (q.round(), r.round(), s.round());
+    let (q_diff, r_diff, s_diff) = (
+        (q_round - q).abs(),
+        (r_round - r).abs(),
+        (s_round - s).abs(),
+    );
+    if q_diff > r_diff && q_diff > s_diff {
+        q_round = -r_round - s_round;
+    } else if r_diff > s_diff {
+        r_round = -q_round - s_round;
+    }
+    format!("{}:{}", q_round as i64, r_round as i64)
+}
+
+/// Computes the great-circle distance in meters between two coordinates with the haversine formula.
+pub fn haversine_dist(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat_a, lat_b) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the coordinates of the center of the hexagonal cell with the given axial coordinates.
+    fn hex_center(q: f64, r: f64, size: f64) -> (f64, f64) {
+        let x = size * 3f64.sqrt() * (q + r / 2.0);
+        let y = size * 1.5 * r;
+        let lat = (y / EARTH_RADIUS).to_degrees();
+        let lon = (x / (EARTH_RADIUS * lat.to_radians().cos())).to_degrees();
+        (lat, lon)
+    }
+
+    #[test]
+    fn geohash_matches_reference() {
+        // Examples of the original geohash.org service
+        assert_eq!(geohash(57.64911, 10.40744, 11), "u4pruydqqvj");
+        assert_eq!(geohash(42.6, -5.6, 5), "ezs42");
+        // Every prefix of a geohash is the geohash of the enclosing cell
+        assert_eq!(geohash(57.64911, 10.40744, 3), "u4p");
+    }
+
+    #[test]
+    fn hex_cell_contains_centers() {
+        let size = 500.0;
+        for (q, r) in [(0, 0), (1, 0), (0, 1), (-2, 3), (4, -1)].iter() {
+            let (lat, lon) = hex_center(*q as f64, *r as f64, size);
+            assert_eq!(hex_cell(lat, lon, size), format!("{}:{}", q, r));
+        }
+        // Points closer to a center than the inner radius of the hexagon fall into its cell
+        let (lat, lon) = hex_center(1.0, 0.0, size);
+        let offset = (0.4 * size / EARTH_RADIUS).to_degrees();
+        assert_eq!(hex_cell(lat + offset, lon, size), "1:0");
+        assert_eq!(hex_cell(lat - offset, lon, size), "1:0");
+    }
+
+    #[test]
+    fn haversine_dist_of_one_degree_of_latitude() {
+        let dist = haversine_dist((48.0, 11.0), (49.0, 11.0));
+        assert!((dist - EARTH_RADIUS * std::f64::consts::PI / 180.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,168 @@
+use crate::cli::Config;
+use crate::trace::{Column, Trace};
+
+use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use rand::{seq::SliceRandom, Rng};
+use std::{collections::BTreeMap, error::Error, str::FromStr};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// Rules to split the history of a user into observed traces and candidate target traces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitStrategy {
+    /// The first share of the chronologically ordered traces is observed, the remaining traces are targets
+    Ratio,
+    /// Traces that end before a calendar date shared by all users are observed, traces that start after it are
+    /// targets
+    Cutoff,
+    /// Every trace can be a target, all other traces of the user are observed
+    LeaveOneOut,
+    /// A random share of the traces is observed regardless of time, the remaining traces are targets
+    Random,
+}
+
+impl FromStr for SplitStrategy {
+    type Err = std::string::ParseError;
+    fn from_str(s: &str) -> Result<SplitStrategy, Self::Err> {
+        match s {
+            "ratio" => Ok(SplitStrategy::Ratio),
+            "cutoff" => Ok(SplitStrategy::Cutoff),
+            "leave_one_out" => Ok(SplitStrategy::LeaveOneOut),
+            "random" => Ok(SplitStrategy::Random),
+            x => panic!("The supplied split strategy does not exist: {:?}", x),
+        }
+    }
+}
+
+/// Indices of the observed traces and the candidate target traces of a user, both in ascending order.
+#[derive(Debug, Clone)]
+pub struct Split {
+    pub observed: Vec<usize>,
+    pub targets: Vec<usize>,
+}
+
+impl Split {
+    /// Returns true if the user can be attacked with the given number of target traces, i.e. if at least one
+    /// observed trace remains once the targets are left out.
+    pub fn is_attackable(&self, target_trace_sample_size: usize) -> bool {
+        let overlaps = self
+            .targets
+            .iter()
+            .any(|idx| self.observed.binary_search(idx).is_ok());
+        let num_shared = if overlaps {
+            target_trace_sample_size.min(self.targets.len())
+        } else {
+            0
+        };
+        !self.targets.is_empty() && self.observed.len() > num_shared
+    }
+}
+
+/// Parses a cutoff given as Unix timestamp, date (`2021-01-15`) or date and time (`2021-01-15T12:00:00`) in the
+/// given time zone.
+pub fn parse_cutoff(s: &str, timezone: Tz) -> Result<f64, Box<dyn Error>> {
+    if let Ok(timestamp) = s.parse::<f64>() {
+        return Ok(timestamp);
+    }
+    let datetime = match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(date) => date.and_hms_opt(0, 0, 0).unwrap(),
+        Err(_) => NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S")?,
+    };
+    match timezone.from_local_datetime(&datetime).earliest() {
+        Some(datetime) => Ok(datetime.timestamp() as f64),
+        None => Err(format!("Error: The cutoff {:?} does not exist in time zone {}.", s, timezone).into()),
+    }
+}
+
+/// Splits the chronologically ordered history of every user according to the configured split strategy.
+///
+/// With a gap of N days, target traces have to start at least N days after the end of the observed window, so that
+/// the decay of the linkage accuracy with the time between observation and attack can be studied.
+pub fn split_histories<C: Column, R: Rng>(
+    config: &Config,
+    user_to_vector_map: &BTreeMap<u32, Vec<Trace<C>>>,
+    rng: &mut R,
+) -> Result<BTreeMap<u32, Split>, Box<dyn Error>> {
+    let strategy = SplitStrategy::from_str(&config.split).unwrap();
+    let gap = config.split_gap_days * SECONDS_PER_DAY;
+    match strategy {
+        SplitStrategy::Ratio | SplitStrategy::Random if !(config.split_ratio > 0. && config.split_ratio < 1.) => {
+            return Err(format!("Error: The split ratio must be between 0 and 1, got {:?}.", config.split_ratio).into())
+        }
+        SplitStrategy::LeaveOneOut | SplitStrategy::Random if gap != 0. => {
+            return Err("Error: A gap between observed and target traces requires a chronological split.".into())
+        }
+        _ => (),
+    }
+    let cutoff = match (strategy, &config.split_cutoff) {
+        (SplitStrategy::Cutoff, Some(cutoff)) => parse_cutoff(cutoff, config.schema.timezone())?,
+        (SplitStrategy::Cutoff, None) => return Err("Error: The cutoff split requires --split_cutoff.".into()),
+        _ => 0.,
+    };
+
+    let mut splits: BTreeMap<u32, Split> = BTreeMap::new();
+    for (user, traces) in user_to_vector_map.iter() {
+        let trace_len = traces.len();
+        let split = match strategy {
+            SplitStrategy::Ratio => {
+                let split_idx = (trace_len as f64 * config.split_ratio) as usize;
+                let window_end = match split_idx {
+                    0 => f64::NEG_INFINITY,
+                    _ => traces[split_idx - 1].end_time,
+                };
+                Split {
+                    observed: (0..split_idx).collect(),
+                    targets: (split_idx..trace_len)
+                        .filter(|idx| gap == 0. || traces[*idx].start_time >= window_end + gap)
+                        .collect(),
+                }
+            }
+            SplitStrategy::Cutoff => Split {
+                observed: (0..trace_len)
+                    .filter(|idx| traces[*idx].end_time <= cutoff)
+                    .collect(),
+                targets: (0..trace_len)
+                    .filter(|idx| traces[*idx].start_time >= cutoff + gap)
+                    .collect(),
+            },
+            SplitStrategy::LeaveOneOut => Split {
+                observed: (0..trace_len).collect(),
+                targets: (0..trace_len).collect(),
+            },
+            SplitStrategy::Random => {
+                let split_idx = (trace_len as f64 * config.split_ratio) as usize;
+                let mut indices: Vec<usize> = (0..trace_len).collect();
+                indices.shuffle(rng);
+                let mut observed = indices[..split_idx].to_vec();
+                let mut targets = indices[split_idx..].to_vec();
+                observed.sort_unstable();
+                targets.sort_unstable();
+                Split {
+                    observed: observed,
+                    targets: targets,
+                }
+            }
+        };
+        splits.insert(*user, split);
+    }
+    Ok(splits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cutoffs_in_local_time() {
+        let berlin = Tz::Europe__Berlin;
+        assert_eq!(parse_cutoff("1616886000", berlin).unwrap(), 1616886000.);
+        // Midnight before the DST transition is still in CET
+        assert_eq!(parse_cutoff("2021-03-28", berlin).unwrap(), 1616886000.);
+        assert_eq!(parse_cutoff("2021-03-28", Tz::UTC).unwrap(), 1616889600.);
+        // The skipped hour does not exist and the repeated hour resolves to its first occurrence in CEST
+        assert!(parse_cutoff("2021-03-28T02:30:00", berlin).is_err());
+        assert_eq!(parse_cutoff("2021-10-31T02:30:00", berlin).unwrap(), 1635640200.);
+        assert!(parse_cutoff("28.03.2021", berlin).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use rand::Rng;
+use serde::Serialize;
+
+/// Quantile of the standard normal distribution for two-sided 95% intervals.
+const Z_95: f64 = 1.959_964;
+
+/// Two-sided 95% confidence interval.
+#[derive(Debug, Clone, Serialize)]
+pub struct Interval {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+/// Confidence intervals of a rate of successful attacks.
+#[derive(Debug, Clone, Serialize)]
+pub struct RateIntervals {
+    pub wilson: Interval,
+    pub bootstrap: Interval,
+}
+
+/// Exact McNemar test of two configurations on the same attacks.
+#[derive(Debug, Clone, Serialize)]
+pub struct McNemar {
+    /// Number of attacks that only succeed with the baseline
+    pub num_baseline_only: usize,
+    /// Number of attacks that only succeed with the candidate
+    pub num_candidate_only: usize,
+    pub p_value: f64,
+}
+
+/// Paired bootstrap test of two configurations on the same attacks.
+#[derive(Debug, Clone, Serialize)]
+pub struct PairedBootstrap {
+    /// Rate of the candidate minus rate of the baseline
+    pub difference: f64,
+    pub interval: Interval,
+    pub p_value: f64,
+}
+
+/// Returns the share of true values, NaN if there are none.
+pub fn rate(outcomes: &[bool]) -> f64 {
+    outcomes.iter().filter(|outcome| **outcome).count() as f64 / outcomes.len() as f64
+}
+
+/// Returns the q-quantile of sorted values by linear interpolation.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+    let position = q * (sorted.len() - 1) as f64;
+    let (lower, upper) = (position.floor() as usize, position.ceil() as usize);
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Percentile interval of the resampled statistics.
+fn percentile_interval(mut resamples: Vec<f64>) -> Interval {
+    resamples.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    Interval {
+        lower: quantile(&resamples, 0.025),
+        upper: quantile(&resamples, 0.975),
+    }
+}
+
+/// Computes the Wilson score interval of a rate, which unlike the normal approximation stays within [0, 1] and is
+/// reliable for rates close to 0 or 1.
+pub fn wilson(outcomes: &[bool]) -> Interval {
+    let n = outcomes.len() as f64;
+    if outcomes.is_empty() {
+        return Interval {
+            lower: f64::NAN,
+            upper: f64::NAN,
+        };
+    }
+    let p = rate(outcomes);
+    let z2 = Z_95 * Z_95;
+    let center = (p + z2 / (2. * n)) / (1. + z2 / n);
+    let half_width = Z_95 / (1. + z2 / n) * (p * (1. - p) / n + z2 / (4. * n * n)).sqrt();
+    Interval {
+        lower: (center - half_width).max(0.),
+        upper: (center + half_width).min(1.),
+    }
+}
+
+/// Computes the percentile bootstrap interval of a rate by resampling the attacks with replacement.
+pub fn bootstrap<R: Rng>(outcomes: &[bool], num_resamples: usize, rng: &mut R) -> Interval {
+    if outcomes.is_empty() {
+        return percentile_interval(Vec::new());
+    }
+    let resamples: Vec<f64> = (0..num_resamples)
+        .map(|_| {
+            let num_successes = (0..outcomes.len())
+                .filter(|_| outcomes[rng.gen_range(0..outcomes.len())])
+                .count();
+            num_successes as f64 / outcomes.len() as f64
+        })
+        .collect();
+    percentile_interval(resamples)
+}
+
+/// Computes both confidence intervals of a rate.
+pub fn rate_intervals<R: Rng>(outcomes: &[bool], num_resamples: usize, rng: &mut R) -> RateIntervals {
+    RateIntervals {
+        wilson: wilson(outcomes),
+        bootstrap: bootstrap(outcomes, num_resamples, rng),
+    }
+}
+
+/// Tests whether two configurations differ in their success on the same attacks. Only the attacks on which they
+/// disagree matter; under the null hypothesis, each of them is equally likely to favor either configuration, so the
+/// two-sided p-value follows from the binomial distribution.
+pub fn mcnemar(baseline: &[bool], candidate: &[bool]) -> McNemar {
+    let num_baseline_only = baseline.iter().zip(candidate).filter(|(a, b)| **a && !**b).count();
+    let num_candidate_only = baseline.iter().zip(candidate).filter(|(a, b)| !**a && **b).count();
+    let n = num_baseline_only + num_candidate_only;
+    // Sum the probabilities of the tail in log space to avoid underflow for many disagreements
+    let mut ln_binomial = 0.;
+    let mut tail = 0.;
+    for i in 0..=num_baseline_only.min(num_candidate_only) {
+        if i > 0 {
+            ln_binomial += ((n - i + 1) as f64).ln() - (i as f64).ln();
+        }
+        tail += (ln_binomial - n as f64 * std::f64::consts::LN_2).exp();
+    }
+    McNemar {
+        num_baseline_only: num_baseline_only,
+        num_candidate_only: num_candidate_only,
+        p_value: (2. * tail).min(1.),
+    }
+}
+
+/// Tests whether two configurations differ in their rate on the same attacks by resampling pairs of outcomes with
+/// replacement. The p-value is twice the share of resampled differences on the less frequent side of zero.
+pub fn paired_bootstrap<R: Rng>(
+    baseline: &[bool],
+    candidate: &[bool],
+    num_resamples: usize,
+    rng: &mut R,
+) -> PairedBootstrap {
+    let differences: Vec<i64> = baseline
+        .iter()
+        .zip(candidate)
+        .map(|(a, b)| *b as i64 - *a as i64)
+        .collect();
+    let n = differences.len();
+    let difference = differences.iter().sum::<i64>() as f64 / n as f64;
+    if n == 0 {
+        return PairedBootstrap {
+            difference: difference,
+            interval: percentile_interval(Vec::new()),
+            p_value: f64::NAN,
+        };
+    }
+    let resamples: Vec<f64> = (0..num_resamples)
+        .map(|_| (0..n).map(|_| differences[rng.gen_range(0..n)]).sum::<i64>() as f64 / n as f64)
+        .collect();
+    let num_non_positive = resamples.iter().filter(|value| **value <= 0.).count();
+    let num_non_negative = resamples.iter().filter(|value| **value >= 0.).count();
+    let p_value = (2. * num_non_positive.min(num_non_negative) as f64 / num_resamples as f64).min(1.);
+    PairedBootstrap {
+        difference: difference,
+        interval: percentile_interval(resamples),
+        p_value: p_value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcomes(num_successes: usize, n: usize) -> Vec<bool> {
+        (0..n).map(|idx| idx < num_successes).collect()
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn wilson_matches_newcombe() {
+        // Examples of Newcombe (1998), Two-sided confidence intervals for the single proportion, Table I
+        let interval = wilson(&outcomes(81, 263));
+        assert_close(interval.lower, 0.2553, 1e-4);
+        assert_close(interval.upper, 0.3662, 1e-4);
+
+        let interval = wilson(&outcomes(15, 148));
+        assert_close(interval.lower, 0.0624, 1e-4);
+        assert_close(interval.upper, 0.1605, 1e-4);
+
+        let interval = wilson(&outcomes(0, 20));
+        assert_close(interval.lower, 0., 1e-12);
+        assert_close(interval.upper, 0.1611, 1e-4);
+    }
+
+    #[test]
+    fn wilson_is_undefined_without_attacks() {
+        let interval = wilson(&[]);
+        assert!(interval.lower.is_nan() && interval.upper.is_nan());
+    }
+
+    #[test]
+    fn mcnemar_matches_binomial() {
+        // 2 attacks only succeed with the baseline and 8 only with the candidate, the other 5 agree. The two-sided
+        // p-value is 2 * (C(10, 0) + C(10, 1) + C(10, 2)) / 2^10 = 2 * 56 / 1024.
+        let baseline: Vec<bool> = [outcomes(2, 2), outcomes(0, 8), outcomes(2, 5)].concat();
+        let candidate: Vec<bool> = [outcomes(0, 2), outcomes(8, 8), outcomes(2, 5)].concat();
+        let test = mcnemar(&baseline, &candidate);
+        assert_eq!(test.num_baseline_only, 2);
+        assert_eq!(test.num_candidate_only, 8);
+        assert_close(test.p_value, 112. / 1024., 1e-12);
+    }
+
+    #[test]
+    fn mcnemar_without_disagreements_is_not_significant() {
+        let baseline = [true, false, true];
+        assert_close(mcnemar(&baseline, &baseline).p_value, 1., 1e-12);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::cli::Config;
+use crate::ranking::{self, Outcome};
+use crate::sample::SamplingMaps;
+use crate::users::UserIds;
+use crate::utils::{self, Scores};
+
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{error::Error, path::Path};
+
+/// Version of the layout of the tables. It has to be incremented whenever tables or columns change.
+const STORE_VERSION: u32 = 1;
+
+const CREATE_TABLES: &str = "
+    CREATE TABLE IF NOT EXISTS runs (
+        run_id TEXT PRIMARY KEY,
+        timestamp TEXT NOT NULL,
+        git_commit TEXT NOT NULL,
+        approach TEXT NOT NULL,
+        path TEXT NOT NULL,
+        num_attacks INTEGER NOT NULL,
+        top_1 REAL,
+        top_10 REAL,
+        top_10_percent REAL,
+        mean_reciprocal_rank REAL,
+        config TEXT NOT NULL,
+        scores TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS attacks (
+        run_id TEXT NOT NULL REFERENCES runs (run_id),
+        user_id TEXT NOT NULL,
+        is_present INTEGER NOT NULL,
+        rank INTEGER,
+        is_top_1 INTEGER NOT NULL,
+        is_top_10 INTEGER NOT NULL,
+        is_top_10_percent INTEGER NOT NULL,
+        best_value REAL,
+        runner_up_value REAL,
+        target_value REAL,
+        candidates TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS attacks_by_run ON attacks (run_id);
+    CREATE TABLE IF NOT EXISTS sampling_maps (
+        run_id TEXT PRIMARY KEY REFERENCES runs (run_id),
+        maps TEXT NOT NULL
+    );
+";
+
+/// Run read back from the store with its configuration and scores as JSON.
+#[derive(Debug, Clone)]
+pub struct StoredRun {
+    pub run_id: String,
+    pub timestamp: String,
+    pub git_commit: String,
+    pub config: serde_json::Value,
+    pub scores: serde_json::Value,
+}
+
+/// SQLite database of the runs of all experiments with their configuration, scores, per-attack rankings and
+/// sampling maps.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Opens the database at the given path and creates the tables if the database is new.
+    pub fn open(path: &str) -> Result<Store, Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        match version {
+            0 => {
+                conn.execute_batch(CREATE_TABLES)?;
+                conn.pragma_update(None, "user_version", STORE_VERSION)?;
+            }
+            STORE_VERSION => (),
+            _ => {
+                return Err(format!(
+                    "Error: The store {:?} has version {:?}, but version {:?} is required.",
+                    path, version, STORE_VERSION
+                )
+                .into())
+            }
+        }
+        Ok(Store { conn: conn })
+    }
+
+    /// Inserts a run with the rankings of its attacks and its sampling maps in a single transaction.
+    pub fn insert_run(
+        &mut self,
+        config: &Config,
+        scores: &Scores,
+        user_ids: &UserIds,
+        maps: Option<&SamplingMaps>,
+    ) -> Result<(), Box<dyn Error>> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "INSERT INTO runs (run_id, timestamp, git_commit, approach, path, num_attacks, top_1, top_10, \
+             top_10_percent, mean_reciprocal_rank, config, scores) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                config.run_id,
+                config.timestamp,
+                utils::GIT_COMMIT,
+                config.approach,
+                config.path,
+                scores.num_attacks as i64,
+                scores.top_1,
+                scores.top_10,
+                scores.top_10_percent,
+                scores.mean_reciprocal_rank,
+                utils::config_to_json(config)?.to_string(),
+                serde_json::to_string(scores)?,
+            ],
+        )?;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT INTO attacks (run_id, user_id, is_present, rank, is_top_1, is_top_10, is_top_10_percent, \
+                 best_value, runner_up_value, target_value, candidates) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            )?;
+            for attack in scores.attacks.iter() {
+                stmt.execute(params![
+                    config.run_id,
+                    user_ids.original(attack.user),
+                    attack.is_present,
+                    attack.rank.map(|rank| rank as i64),
+                    attack.is_top_1,
+                    attack.is_top_10,
+                    attack.is_top_10_percent,
+                    attack.best_value,
+                    attack.runner_up_value,
+                    attack.target_value,
+                    serde_json::to_string(&ranking::candidates(user_ids, attack))?,
+                ])?;
+            }
+        }
+        if let Some(maps) = maps {
+            tx.execute(
+                "INSERT INTO sampling_maps (run_id, maps) VALUES (?1, ?2)",
+                params![config.run_id, serde_json::to_string(&maps.to_original(user_ids)?)?],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns all runs in chronological order.
+    pub fn runs(&self) -> Result<Vec<StoredRun>, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT run_id, timestamp, git_commit, config, scores FROM runs ORDER BY timestamp, run_id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+            ))
+        })?;
+        let mut runs: Vec<StoredRun> = Vec::new();
+        for row in rows {
+            let (run_id, timestamp, git_commit, config, scores) = row?;
+            runs.push(StoredRun {
+                run_id: run_id,
+                timestamp: timestamp,
+                git_commit: git_commit,
+                config: serde_json::from_str(&config)?,
+                scores: serde_json::from_str(&scores)?,
+            });
+        }
+        Ok(runs)
+    }
+
+    /// Returns the run whose id starts with the given prefix, which has to be unique.
+    pub fn find_run(&self, prefix: &str) -> Result<StoredRun, Box<dyn Error>> {
+        let mut matches: Vec<StoredRun> = self
+            .runs()?
+            .into_iter()
+            .filter(|run| run.run_id.starts_with(prefix))
+            .collect();
+        match matches.len() {
+            1 => Ok(matches.remove(0)),
+            0 => Err(format!("Error: No run matches {:?}.", prefix).into()),
+            n => Err(format!("Error: {:?} runs match {:?}.", n, prefix).into()),
+        }
+    }
+
+    /// Returns the outcomes of the attacks of a run.
+    pub fn outcomes(&self, run_id: &str) -> Result<Vec<Outcome>, Box<dyn Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT user_id, is_present, rank, is_top_1, is_top_10, is_top_10_percent FROM attacks WHERE run_id = ?1",
+        )?;
+        let rows = stmt.query_map(params![run_id], |row| {
+            Ok(Outcome {
+                user_id: row.get(0)?,
+                is_present: row.get(1)?,
+                rank: row.get::<_, Option<i64>>(2)?.map(|rank| rank as usize),
+                is_top_1: row.get(3)?,
+                is_top_10: row.get(4)?,
+                is_top_10_percent: row.get(5)?,
+            })
+        })?;
+        let mut outcomes: Vec<Outcome> = Vec::new();
+        for row in rows {
+            outcomes.push(row?);
+        }
+        Ok(outcomes)
+    }
+
+    /// Returns the sampling maps of a run with the original user ids if they were stored.
+    pub fn sampling_maps(&self, run_id: &str) -> Result<Option<SamplingMaps<String>>, Box<dyn Error>> {
+        let maps: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT maps FROM sampling_maps WHERE run_id = ?1",
+                params![run_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match maps {
+            Some(maps) => Ok(Some(serde_json::from_str(&maps)?)),
+            None => Ok(None),
+        }
+    }
+}
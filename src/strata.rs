@@ -0,0 +1,273 @@
+use crate::trace::Trace;
+use crate::utils::Attack;
+
+use rand::{seq::IteratorRandom, Rng};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Measure of the activity of a user by which users are stratified.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activity {
+    /// Number of traces of the user
+    NumTraces,
+    /// Total distance in meters covered by the traces of the user
+    Distance,
+    /// Time in seconds between the start of the first and the end of the last trace of the user
+    TimeSpan,
+}
+
+impl FromStr for Activity {
+    type Err = std::string::ParseError;
+    fn from_str(s: &str) -> Result<Activity, Self::Err> {
+        match s {
+            "num_traces" => Ok(Activity::NumTraces),
+            "distance" => Ok(Activity::Distance),
+            "time_span" => Ok(Activity::TimeSpan),
+            x => panic!("The supplied activity measure does not exist: {:?}", x),
+        }
+    }
+}
+
+impl Activity {
+    /// Measures the activity of a user from the chronologically ordered traces of the user.
+    fn measure<C>(&self, traces: &[Trace<C>]) -> f64 {
+        match self {
+            Activity::NumTraces => traces.len() as f64,
+            Activity::Distance => traces.iter().map(|trace| trace.distance).sum(),
+            Activity::TimeSpan => match (traces.first(), traces.last()) {
+                (Some(first), Some(last)) => last.end_time - first.start_time,
+                _ => 0.,
+            },
+        }
+    }
+}
+
+/// Range of activity of the users in a stratum.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stratum {
+    pub lower: f64,
+    pub upper: f64,
+    pub num_users: usize,
+}
+
+/// Assignment of the users to strata by their activity. The users are identified by their sequential ids, or by
+/// their original ids when exported.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stratification<U: Ord = u32> {
+    pub activity: String,
+    pub strata: Vec<Stratum>,
+    pub user_to_stratum_map: BTreeMap<U, usize>,
+}
+
+/// Accuracy scores of the attacks on the target users of a single stratum.
+#[derive(Debug, Clone, Serialize)]
+pub struct StratumScores {
+    pub stratum: usize,
+    pub lower: f64,
+    pub upper: f64,
+    pub num_attacks: usize,
+    pub top_1: f64,
+    pub top_10: f64,
+}
+
+impl Stratification {
+    /// Divides the given users into strata of (almost) equal size, from the least to the most active users. Users
+    /// with the same activity are ordered by their id.
+    pub fn new<C>(
+        activity: &str,
+        users: &[u32],
+        user_to_vector_map: &BTreeMap<u32, Vec<Trace<C>>>,
+        num_strata: usize,
+    ) -> Stratification {
+        let measure = Activity::from_str(activity).unwrap();
+        let mut activities: Vec<(f64, u32)> = users
+            .iter()
+            .map(|user| (measure.measure(&user_to_vector_map[user]), *user))
+            .collect();
+        activities.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let num_strata = num_strata.min(activities.len()).max(1);
+        let mut strata: Vec<Stratum> = Vec::with_capacity(num_strata);
+        let mut user_to_stratum_map: BTreeMap<u32, usize> = BTreeMap::new();
+        for (rank, (value, user)) in activities.iter().enumerate() {
+            let stratum = rank * num_strata / activities.len();
+            if stratum == strata.len() {
+                strata.push(Stratum {
+                    lower: *value,
+                    upper: *value,
+                    num_users: 0,
+                });
+            }
+            strata[stratum].upper = *value;
+            strata[stratum].num_users += 1;
+            user_to_stratum_map.insert(*user, stratum);
+        }
+        Stratification {
+            activity: activity.to_string(),
+            strata: strata,
+            user_to_stratum_map: user_to_stratum_map,
+        }
+    }
+
+    /// Samples the same number of users from each stratum, so that the sample is not dominated by users of moderate
+    /// activity. Strata with fewer users contribute all of their users. Without strata, no user is sampled.
+    pub fn choose_users<R: Rng>(&self, users: &[u32], rng: &mut R, user_sample_size: usize) -> Vec<u32> {
+        let num_strata = self.strata.len();
+        if num_strata == 0 {
+            log::warn!("There are no strata to sample users from.");
+            return Vec::new();
+        }
+        let mut sampled_users: Vec<u32> = Vec::with_capacity(user_sample_size);
+        for stratum in 0..num_strata {
+            let stratum_size = user_sample_size / num_strata + (stratum < user_sample_size % num_strata) as usize;
+            let stratum_users = users
+                .iter()
+                .filter(|user| self.user_to_stratum_map.get(user) == Some(&stratum))
+                .choose_multiple(rng, stratum_size);
+            if stratum_users.len() < stratum_size {
+                log::warn!(
+                    "Stratum {:?} only holds {:?} of {:?} users to sample.",
+                    stratum,
+                    stratum_users.len(),
+                    stratum_size
+                );
+            }
+            sampled_users.extend(stratum_users.into_iter().cloned());
+        }
+        sampled_users
+    }
+
+    /// Computes the top-1 and top-10 accuracy of the attacks on the present target users of each stratum.
+    pub fn scores(&self, attacks: &[Attack]) -> Vec<StratumScores> {
+        self.strata
+            .iter()
+            .enumerate()
+            .map(|(idx, stratum)| {
+                let stratum_attacks: Vec<&Attack> = attacks
+                    .iter()
+                    .filter(|attack| {
+                        attack.is_present && self.user_to_stratum_map.get(&attack.user) == Some(&idx)
+                    })
+                    .collect();
+                let num_attacks = stratum_attacks.len();
+                let share = |count: usize| count as f64 / num_attacks as f64;
+                StratumScores {
+                    stratum: idx,
+                    lower: stratum.lower,
+                    upper: stratum.upper,
+                    num_attacks: num_attacks,
+                    top_1: share(stratum_attacks.iter().filter(|attack| attack.is_top_1).count()),
+                    top_10: share(stratum_attacks.iter().filter(|attack| attack.is_top_10).count()),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Creates the given number of consecutive daily traces of 10 km each.
+    fn traces(num_traces: usize) -> Vec<Trace<()>> {
+        (0..num_traces)
+            .map(|idx| Trace {
+                columns: Vec::new(),
+                start_time: idx as f64 * 86_400.,
+                end_time: idx as f64 * 86_400. + 3_600.,
+                num_points: 10,
+                distance: 10_000.,
+            })
+            .collect()
+    }
+
+    /// User `n` has `n` traces.
+    fn user_to_vector_map(num_users: u32) -> BTreeMap<u32, Vec<Trace<()>>> {
+        (1..=num_users).map(|user| (user, traces(user as usize))).collect()
+    }
+
+    fn attack(user: u32, is_present: bool, is_top_1: bool) -> Attack {
+        Attack {
+            user: user,
+            is_present: is_present,
+            is_top_1: is_top_1,
+            is_top_10: is_present,
+            is_top_10_percent: is_top_1,
+            best_value: 0.,
+            runner_up_value: None,
+            rank: None,
+            target_value: None,
+            candidates: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn divides_users_by_activity() {
+        let map = user_to_vector_map(7);
+        let stratification = Stratification::new("num_traces", &[5, 2, 7, 1, 4, 6, 3], &map, 3);
+        let sizes: Vec<usize> = stratification.strata.iter().map(|stratum| stratum.num_users).collect();
+        assert_eq!(sizes, [3, 2, 2]);
+        let ranges: Vec<(f64, f64)> = stratification.strata.iter().map(|s| (s.lower, s.upper)).collect();
+        assert_eq!(ranges, [(1., 3.), (4., 5.), (6., 7.)]);
+        let strata: Vec<usize> = stratification.user_to_stratum_map.values().cloned().collect();
+        assert_eq!(strata, [0, 0, 0, 1, 1, 2, 2]);
+
+        // The distance grows with the number of traces and the time span ends with the last trace
+        let stratification = Stratification::new("distance", &[1, 2, 3, 4], &map, 2);
+        assert_eq!((stratification.strata[1].lower, stratification.strata[1].upper), (30_000., 40_000.));
+        let stratification = Stratification::new("time_span", &[1, 2], &map, 2);
+        assert_eq!(stratification.strata[0].upper, 3_600.);
+        assert_eq!(stratification.strata[1].upper, 86_400. + 3_600.);
+    }
+
+    #[test]
+    fn orders_ties_by_id_and_caps_strata() {
+        let map: BTreeMap<u32, Vec<Trace<()>>> = (1..=4).map(|user| (user, traces(2))).collect();
+        let stratification = Stratification::new("num_traces", &[4, 3, 2, 1], &map, 2);
+        assert_eq!(stratification.user_to_stratum_map[&1], 0);
+        assert_eq!(stratification.user_to_stratum_map[&2], 0);
+        assert_eq!(stratification.user_to_stratum_map[&4], 1);
+
+        let stratification = Stratification::new("num_traces", &[1, 2], &map, 5);
+        assert_eq!(stratification.strata.len(), 2);
+    }
+
+    #[test]
+    fn samples_users_evenly_from_strata() {
+        let map = user_to_vector_map(12);
+        let users: Vec<u32> = map.keys().cloned().collect();
+        let stratification = Stratification::new("num_traces", &users, &map, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let sampled = stratification.choose_users(&users, &mut rng, 7);
+        let mut per_stratum = [0; 3];
+        for user in sampled.iter() {
+            per_stratum[stratification.user_to_stratum_map[user]] += 1;
+        }
+        assert_eq!(per_stratum, [3, 2, 2]);
+
+        // A small stratum contributes all of its users
+        let sampled = stratification.choose_users(&[1, 2, 5, 6, 7, 8, 9, 10, 11, 12], &mut rng, 9);
+        assert_eq!(sampled.iter().filter(|user| **user <= 4).count(), 2);
+        assert_eq!(sampled.len(), 8);
+
+        let empty = Stratification::new::<()>("num_traces", &[], &BTreeMap::new(), 3);
+        assert!(empty.strata.is_empty());
+        assert!(empty.choose_users(&users, &mut rng, 5).is_empty());
+    }
+
+    #[test]
+    fn scores_present_targets_per_stratum() {
+        let map = user_to_vector_map(4);
+        let stratification = Stratification::new("num_traces", &[1, 2, 3, 4], &map, 2);
+        let attacks = [
+            attack(1, true, true),
+            attack(2, true, false),
+            attack(3, true, true),
+            attack(4, false, false),
+        ];
+        let scores = stratification.scores(&attacks);
+        assert_eq!((scores[0].num_attacks, scores[0].top_1, scores[0].top_10), (2, 0.5, 1.));
+        assert_eq!((scores[1].num_attacks, scores[1].top_1), (1, 1.));
+    }
+}
@@ -0,0 +1,62 @@
+use crate::schema::{Attribute, Schema};
+
+use serde::{Deserialize, Serialize};
+
+/// Integer value of missing (empty) values in all columns except for those of timestamp attributes.
+pub const MISSING: u32 = 0;
+
+/// A mobility trace that stores the values of each attribute in a separate column.
+///
+/// The columns are indexed by the position of the attribute in the schema. The histogram-based and the sequence
+/// alignment-based approach only differ in the type of column they use.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trace<C> {
+    pub columns: Vec<C>,
+    pub start_time: f64,
+    pub end_time: f64,
+    pub num_points: usize,
+    /// Distance in meters between consecutive points, zero if the coordinates are not read
+    pub distance: f64,
+}
+
+/// Storage for the values of a single attribute within a trace.
+pub trait Column: Clone {
+    /// Creates an empty column for the given attribute.
+    fn new(attr: &Attribute) -> Self;
+
+    /// Adds the (integer-mapped) value of a single point to the column.
+    fn push(&mut self, attr: &Attribute, value: u32);
+
+    /// Calls the closure with each distinct value of the column and the number of its occurrences.
+    fn for_each_value<F: FnMut(u32, u32)>(&self, f: F);
+
+    /// Replaces each value of the column by the value the closure maps it to.
+    fn map_values<F: Fn(u32) -> u32>(&mut self, f: F);
+}
+
+impl<C: Column> Trace<C> {
+    /// Creates an empty trace with one column per attribute of the schema.
+    pub fn new(schema: &Schema, start_time: f64) -> Trace<C> {
+        Trace {
+            columns: schema.attributes.iter().map(|attr| C::new(attr)).collect(),
+            start_time: start_time,
+            end_time: start_time,
+            num_points: 0,
+            distance: 0.0,
+        }
+    }
+
+    /// Adds a single point, given by its timestamp and one value per attribute, to the trace.
+    pub fn push(&mut self, schema: &Schema, timestamp: f64, values: &[u32]) {
+        self.end_time = timestamp;
+        self.num_points += 1;
+        for ((column, attr), value) in self
+            .columns
+            .iter_mut()
+            .zip(schema.attributes.iter())
+            .zip(values.iter())
+        {
+            column.push(attr, *value);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use csv::WriterBuilder;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, convert::TryFrom, error::Error, path::Path};
+
+/// Bidirectional mapping between the sequential user ids used internally and the original user ids of the dataset.
+///
+/// Ids are assigned in the order in which the users appear in the input. If the input is not grouped by user and
+/// not sorted either, a user that appears in several blocks obtains one id per block. Only the original ids are
+/// serialized since the reverse mapping can be rebuilt from them.
+#[derive(Debug, Default)]
+pub struct UserIds {
+    originals: Vec<String>,
+    ids: HashMap<String, u32>,
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    id: u32,
+    user_id: &'a str,
+}
+
+impl UserIds {
+    /// Assigns the next sequential id to the given original user id.
+    pub fn push(&mut self, original: &str) -> u32 {
+        let id = u32::try_from(self.originals.len()).unwrap();
+        self.originals.push(original.to_string());
+        self.ids.entry(original.to_string()).or_insert(id);
+        id
+    }
+
+    /// Returns the original user id for a sequential id.
+    pub fn original(&self, id: u32) -> &str {
+        &self.originals[id as usize]
+    }
+
+    /// Returns the (first) sequential id assigned to an original user id.
+    pub fn id(&self, original: &str) -> Option<u32> {
+        self.ids.get(original).cloned()
+    }
+
+    /// Writes the mapping of the given sequential ids to a CSV file with the columns `id` and `user_id`.
+    pub fn write_to_path<'a>(
+        &self,
+        path: &str,
+        ids: impl Iterator<Item = &'a u32>,
+    ) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut wtr = WriterBuilder::new().has_headers(true).from_path(path)?;
+        for id in ids {
+            wtr.serialize(Row {
+                id: *id,
+                user_id: self.original(*id),
+            })?;
+        }
+        wtr.flush()?;
+        Ok(())
+    }
+}
+
+impl Serialize for UserIds {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.originals.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for UserIds {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let originals = Vec::<String>::deserialize(deserializer)?;
+        let mut user_ids = UserIds::default();
+        for original in originals.iter() {
+            user_ids.push(original);
+        }
+        Ok(user_ids)
+    }
+}
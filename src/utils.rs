@@ -1,38 +1,68 @@
 use crate::cli::Config;
+use crate::openworld::OpenWorldScores;
+use crate::stats::{self, RateIntervals};
+use crate::strata::{Stratification, StratumScores};
 
-use csv::WriterBuilder;
+use csv::{ReaderBuilder, WriterBuilder};
 use indexmap::set::IndexSet;
 use ordered_float::OrderedFloat;
+use rand::{rngs::StdRng, SeedableRng};
 use serde::Serialize;
-use std::{collections::HashMap, error::Error};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
 
-const EVAL_PATH: &str = "tmp/evaluation";
+/// Version of the layout of the evaluation output. It has to be incremented whenever fields are added, removed or
+/// renamed or their meaning changes, so that outputs of different versions are not mixed up. Version 2 counts the
+/// top-k accuracies per candidate user and rounds the top 10 percent up.
+const OUTPUT_SCHEMA_VERSION: u32 = 2;
+
+/// Commit of the repository the binary was built from, empty if unknown.
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
 
 /// Normalizes the values of a given vector.
 pub fn normalize_vector(vector: &mut [f64]) {
     let norm = vector.iter().map(|x| *x * *x).sum::<f64>().sqrt();
     if norm > 0. {
         for i in vector.iter_mut() {
-            *i = *i / norm;
+            *i /= norm;
         }
     }
 }
 
 /// Generates a vector of fixed size from a given frequency map. The size depends on the size set of unique values.
 pub fn gen_vector_from_freq_map(
-    type_to_freq_map: &HashMap<String, u32>,
-    set: &IndexSet<String>,
+    type_to_freq_map: &HashMap<u32, u32>,
+    set: &IndexSet<u32>,
 ) -> Vec<u32> {
     let mut vector: Vec<u32> = vec![0; set.len()];
-    for (key, value) in type_to_freq_map.into_iter() {
-        vector[set.get_full(key).unwrap().0] = value.clone();
+    for (key, value) in type_to_freq_map.iter() {
+        vector[set.get_full(key).unwrap().0] = *value;
     }
     vector
 }
 
-/// Returns true if the target value can be found in the top k of values.
-pub fn is_target_in_top_k(user_target: &u32, tuples: &[(u32, OrderedFloat<f64>)]) -> bool {
-    tuples.iter().any(|(a, _)| a == user_target)
+/// Reduces the sorted results of an attack to the best value of every candidate user, in the order of the results.
+pub fn rank_candidates(tuples: &[(u32, OrderedFloat<f64>)]) -> Vec<(u32, f64)> {
+    let mut seen: HashSet<u32> = HashSet::new();
+    tuples
+        .iter()
+        .filter(|(user, _)| seen.insert(*user))
+        .map(|(user, value)| (*user, value.into_inner()))
+        .collect()
+}
+
+/// Generates a random version 4 UUID.
+pub fn gen_run_id() -> String {
+    let mut bytes: [u8; 16] = rand::random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
 }
 
 /// Returns the most frequent element in a given vector of values. The values can be of arbitrary type.
@@ -41,20 +71,31 @@ where
     T: std::cmp::Eq + std::hash::Hash + Copy,
 {
     let mut map = HashMap::new();
-    for e in vector.into_iter() {
+    for e in vector.iter() {
         *map.entry(e).or_insert(0) += 1;
     }
     let option = map.into_iter().max_by_key(|(_, v)| *v).map(|(k, _)| k);
-    let most_frequent_ele = *option.unwrap();
-    most_frequent_ele
+    *option.unwrap()
 }
 
 /// Calculates the mean for a vector of values.
 pub fn mean(data: &[f64]) -> f64 {
     let sum = data.iter().sum::<f64>();
     let count = data.len();
-    let mean = sum / count as f64;
-    mean
+    sum / count as f64
+}
+
+/// Calculates the median for a vector of values. The values are sorted in place.
+pub fn median(data: &mut [f64]) -> f64 {
+    data.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap());
+    let count = data.len();
+    if count == 0 {
+        f64::NAN
+    } else if count.is_multiple_of(2) {
+        (data[count / 2 - 1] + data[count / 2]) / 2.
+    } else {
+        data[count / 2]
+    }
 }
 
 /// Calculates the standard deviation for a vector of values.
@@ -62,15 +103,237 @@ pub fn std_deviation(data: &[f64]) -> f64 {
     let data_mean = mean(data);
     let count = data.len();
     let variance = data.iter().map(|value| {
-        let diff = data_mean - (*value as f64);
+        let diff = data_mean - *value;
         diff * diff
     }).sum::<f64>() / count as f64;
     variance.sqrt()
 }
 
+/// Outcome of a single linkage attack.
+#[derive(Debug, Clone)]
+pub struct Attack {
+    pub user: u32,
+    /// True if the target user is among the candidates, which is always the case in a closed world
+    pub is_present: bool,
+    pub is_top_1: bool,
+    pub is_top_10: bool,
+    pub is_top_10_percent: bool,
+    /// Distance (frequency-based) or alignment score (sequence alignment-based) of the best ranked candidate
+    pub best_value: f64,
+    /// Value of the second best ranked candidate user
+    pub runner_up_value: Option<f64>,
+    /// Rank of the target user among the candidate users, starting at 1, if the target user is present
+    pub rank: Option<usize>,
+    /// Best value of the target user if the target user is present
+    pub target_value: Option<f64>,
+    /// Best ranked candidate users with their values, at most `ranking_top_n` of them
+    pub candidates: Vec<(u32, f64)>,
+}
+
+impl Attack {
+    /// Records the outcome of an attack on the target user from the sorted results, best first. The top 10 percent
+    /// are taken of the given number of sampled users.
+    pub fn new(
+        config: &Config,
+        user_target: &u32,
+        is_present: bool,
+        result_tuples: &[(u32, OrderedFloat<f64>)],
+        num_users: usize,
+    ) -> Attack {
+        let mut candidates = rank_candidates(result_tuples);
+        let target_position = candidates.iter().position(|(user, _)| user == user_target);
+        let runner_up_value = candidates.get(1).map(|(_, value)| *value);
+        let target_value = target_position.map(|idx| candidates[idx].1);
+        candidates.truncate(config.ranking_top_n);
+        let rank = target_position.map(|idx| idx + 1);
+        // The top-k flags count candidate users like the rank, not their sampled traces
+        let is_within = |k: usize| rank.is_some_and(|rank| rank <= k);
+        Attack {
+            user: *user_target,
+            is_present: is_present,
+            is_top_1: is_within(1),
+            is_top_10: is_within(10),
+            is_top_10_percent: is_within(top_10_percent_cutoff(num_users)),
+            // Without any candidates, there is no best value and the attack is rejected in the open world
+            best_value: result_tuples.first().map_or(f64::NAN, |(_, value)| value.into_inner()),
+            runner_up_value: runner_up_value,
+            rank: rank,
+            target_value: target_value,
+            candidates: candidates,
+        }
+    }
+}
+
+/// Returns the rank up to which an attack counts as top 10 percent among the given number of users, i.e. 10 percent
+/// of them rounded up and at least the first rank, so that small samples do not end up with an empty top 10 percent.
+pub fn top_10_percent_cutoff(num_users: usize) -> usize {
+    std::cmp::max(1, num_users.div_ceil(10))
+}
+
+/// Accuracy scores of a set of linkage attacks. The top-k scores only cover the attacks on target users that are
+/// among the candidates.
+#[derive(Debug, Clone, Serialize)]
+pub struct Scores {
+    pub num_attacks: usize,
+    pub top_1: f64,
+    pub top_1_std: f64,
+    pub top_10: f64,
+    pub top_10_std: f64,
+    pub top_10_percent: f64,
+    pub top_10_percent_std: f64,
+    /// Mean of the reciprocal ranks of the target users, where a target user without rank counts as 0
+    pub mean_reciprocal_rank: f64,
+    /// Median rank of the target users with a rank
+    pub median_rank: f64,
+    /// Identification rate at each configured rank k
+    pub top_k: BTreeMap<usize, f64>,
+    /// Cumulative match characteristic (CMC) curve, i.e. the identification rate at the ranks 1, 2, ... up to the
+    /// worst rank of a target user, beyond which it stays constant
+    pub cmc: Vec<f64>,
+    /// Wilson score and bootstrap confidence intervals of the top-k scores
+    pub top_1_ci: RateIntervals,
+    pub top_10_ci: RateIntervals,
+    pub top_10_percent_ci: RateIntervals,
+    pub top_k_ci: BTreeMap<usize, RateIntervals>,
+    /// Scores of the accept/reject decisions, only set in the open-world mode or with a rejection threshold
+    pub open_world: Option<OpenWorldScores>,
+    /// Scores per stratum of users, only set if the users are sampled by activity
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub strata: Vec<StratumScores>,
+    #[serde(skip)]
+    pub attacks: Vec<Attack>,
+}
+
+impl Scores {
+    /// Computes the scores from the results of the linkage attacks, i.e. whether the target user is ranked first,
+    /// among the top 10 and among the top 10 percent, as well as the scores based on the rank of the target user
+    /// among the candidate users. `higher_is_better` tells whether the best candidate has the highest or the lowest
+    /// value.
+    pub fn new(config: &Config, result_list: &[Attack], higher_is_better: bool) -> Scores {
+        let mut top_1_list: Vec<f64> = Vec::with_capacity(result_list.len());
+        let mut top_10_list: Vec<f64> = Vec::with_capacity(result_list.len());
+        let mut top_10_percent_list: Vec<f64> = Vec::with_capacity(result_list.len());
+        for attack in result_list.iter().filter(|attack| attack.is_present) {
+            if attack.is_top_1 {
+                top_1_list.push(1.0);
+            } else {
+                top_1_list.push(0.0);
+            }
+            if attack.is_top_10 {
+                top_10_list.push(1.0);
+            } else {
+                top_10_list.push(0.0);
+            }
+            if attack.is_top_10_percent {
+                top_10_percent_list.push(1.0);
+            } else {
+                top_10_percent_list.push(0.0);
+            }
+        }
+
+        let ranks: Vec<Option<usize>> = result_list
+            .iter()
+            .filter(|attack| attack.is_present)
+            .map(|attack| attack.rank)
+            .collect();
+        let reciprocal_ranks: Vec<f64> = ranks
+            .iter()
+            .map(|rank| rank.map_or(0., |rank| 1. / rank as f64))
+            .collect();
+        let mut known_ranks: Vec<f64> = ranks.iter().filter_map(|rank| rank.map(|rank| rank as f64)).collect();
+        let mut rank_counts: Vec<usize> = vec![0; ranks.iter().flatten().max().cloned().unwrap_or(0)];
+        for rank in ranks.iter().flatten() {
+            rank_counts[rank - 1] += 1;
+        }
+        let cmc: Vec<f64> = rank_counts
+            .iter()
+            .scan(0, |num_identified, count| {
+                *num_identified += count;
+                Some(*num_identified as f64 / ranks.len() as f64)
+            })
+            .collect();
+        let top_k: BTreeMap<usize, f64> = config
+            .top_k
+            .iter()
+            .map(|k| match cmc.len() {
+                0 => (*k, f64::NAN),
+                len => (*k, cmc[(*k).min(len) - 1]),
+            })
+            .collect();
+
+        // Resample with a fixed seed so that the intervals are reproducible
+        let mut rng = StdRng::seed_from_u64(config.seed);
+        let present: Vec<&Attack> = result_list.iter().filter(|attack| attack.is_present).collect();
+        let mut intervals = |is_success: &dyn Fn(&Attack) -> bool| {
+            let outcomes: Vec<bool> = present.iter().map(|attack| is_success(attack)).collect();
+            stats::rate_intervals(&outcomes, config.bootstrap_samples, &mut rng)
+        };
+        let top_1_ci = intervals(&|attack| attack.is_top_1);
+        let top_10_ci = intervals(&|attack| attack.is_top_10);
+        let top_10_percent_ci = intervals(&|attack| attack.is_top_10_percent);
+        let top_k_ci: BTreeMap<usize, RateIntervals> = config
+            .top_k
+            .iter()
+            .map(|k| (*k, intervals(&|attack| attack.rank.is_some_and(|rank| rank <= *k))))
+            .collect();
+
+        Scores {
+            num_attacks: result_list.len(),
+            top_1: mean(&top_1_list),
+            top_1_std: std_deviation(&top_1_list),
+            top_10: mean(&top_10_list),
+            top_10_std: std_deviation(&top_10_list),
+            top_10_percent: mean(&top_10_percent_list),
+            top_10_percent_std: std_deviation(&top_10_percent_list),
+            mean_reciprocal_rank: mean(&reciprocal_ranks),
+            median_rank: median(&mut known_ranks),
+            top_k: top_k,
+            cmc: cmc,
+            top_1_ci: top_1_ci,
+            top_10_ci: top_10_ci,
+            top_10_percent_ci: top_10_percent_ci,
+            top_k_ci: top_k_ci,
+            open_world: if config.open_world_fraction > 0. || config.rejection_threshold.is_some() {
+                Some(OpenWorldScores::new(
+                    result_list,
+                    config.rejection_threshold,
+                    higher_is_better,
+                ))
+            } else {
+                None
+            },
+            strata: Vec::new(),
+            attacks: result_list.to_vec(),
+        }
+    }
+}
+
+impl Scores {
+    /// Adds the scores per stratum if the users are sampled by activity.
+    pub fn add_strata(&mut self, stratification: Option<&Stratification>) {
+        if let Some(stratification) = stratification {
+            self.strata = stratification.scores(&self.attacks);
+        }
+    }
+}
+
+/// Record of the JSON and JSON Lines output.
+#[derive(Serialize)]
+struct Record<'a> {
+    schema_version: u32,
+    run_id: &'a str,
+    timestamp: &'a str,
+    git_commit: &'a str,
+    config: serde_json::Value,
+    scores: &'a Scores,
+}
 
 #[derive(Serialize)]
 struct Row {
+    schema_version: u32,
+    run_id: String,
+    timestamp: String,
+    git_commit: String,
     delay_limit: f64,
     max_trace_len: usize,
     min_trace_len: usize,
@@ -83,6 +346,7 @@ struct Row {
     target_trace_sample_size: usize,
     approach: String,
     fields: String,
+    missing: String,
     typical: bool,
     dependent: bool,
     metric: String,
@@ -95,31 +359,61 @@ struct Row {
     top_10_std: f64,
     top_10_percent: f64,
     top_10_percent_std: f64,
+    top_1_wilson_lower: f64,
+    top_1_wilson_upper: f64,
+    top_1_bootstrap_lower: f64,
+    top_1_bootstrap_upper: f64,
+    top_10_wilson_lower: f64,
+    top_10_wilson_upper: f64,
+    top_10_bootstrap_lower: f64,
+    top_10_bootstrap_upper: f64,
+    top_10_percent_wilson_lower: f64,
+    top_10_percent_wilson_upper: f64,
+    top_10_percent_bootstrap_lower: f64,
+    top_10_percent_bootstrap_upper: f64,
+    mean_reciprocal_rank: f64,
+    median_rank: f64,
+    top_k: String,
+    top_k_wilson: String,
+    top_k_bootstrap: String,
+    cmc: String,
+    open_world_fraction: f64,
+    rejection_threshold: Option<f64>,
+    true_match_rate: Option<f64>,
+    false_match_rate: Option<f64>,
+    rejection_accuracy: Option<f64>,
+    equal_error_rate: Option<f64>,
+    /// Full configuration as JSON
+    config: String,
 }
 
-/// Writes the performance scores as well as the configuration to file.
-pub fn write_to_file(
-    config: &Config,
-    top_1: f64,
-    top_1_std: f64,
-    top_10: f64,
-    top_10_std: f64,
-    top_10_percent: f64,
-    top_10_percent_std: f64,
-) -> Result<(), Box<dyn Error>> {
-    let file = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(EVAL_PATH)
-        .unwrap();
-
-    let mut wtr = WriterBuilder::new()
-        .delimiter(b',')
-        .has_headers(true)
-        .from_writer(file);
-
-    wtr.serialize(Row {
+/// Formats the intervals per rank k as `k:[lower,upper]` separated by semicolons.
+fn format_intervals(
+    intervals: &BTreeMap<usize, RateIntervals>,
+    interval: fn(&RateIntervals) -> &stats::Interval,
+) -> String {
+    intervals
+        .iter()
+        .map(|(k, intervals)| format!("{}:[{},{}]", k, interval(intervals).lower, interval(intervals).upper))
+        .collect::<Vec<String>>()
+        .join(";")
+}
+
+/// Serializes the configuration to JSON with the names of the data fields instead of their indices.
+pub fn config_to_json(config: &Config) -> Result<serde_json::Value, Box<dyn Error>> {
+    let mut value = serde_json::to_value(config)?;
+    value["fields"] = serde_json::to_value(config.schema.attribute_names(&config.fields))?;
+    Ok(value)
+}
+
+/// Assembles the row of the CSV output.
+fn gen_row(config: &Config, scores: &Scores) -> Result<Row, Box<dyn Error>> {
+    let open_world = scores.open_world.as_ref();
+    Ok(Row {
+        schema_version: OUTPUT_SCHEMA_VERSION,
+        run_id: config.run_id.clone(),
+        timestamp: config.timestamp.clone(),
+        git_commit: GIT_COMMIT.to_string(),
         delay_limit: config.delay_limit,
         max_trace_len: config.max_trace_len,
         min_trace_len: config.min_trace_len,
@@ -131,19 +425,135 @@ pub fn write_to_file(
         path: config.path.to_string(),
         seed: config.seed,
         approach: config.approach.to_string(),
-        fields: format!("{:?}", &config.fields),
+        fields: format!("{:?}", config.schema.attribute_names(&config.fields)),
+        missing: format!(
+            "{:?}",
+            config
+                .fields
+                .iter()
+                .map(|idx| config.schema.attributes[*idx].missing_policy())
+                .collect::<Vec<_>>()
+        ),
         typical: config.typical,
         dependent: config.dependent,
         metric: config.metric.to_string(),
         strategy: config.strategy.to_string(),
         scoring_matrix: format!("{:?}", &config.scoring_matrix),
         scope: config.scope.to_string(),
-        top_1: top_1,
-        top_1_std: top_1_std,
-        top_10: top_10,
-        top_10_std: top_10_std,
-        top_10_percent: top_10_percent,
-        top_10_percent_std: top_10_percent_std,
-    })?;
+        top_1: scores.top_1,
+        top_1_std: scores.top_1_std,
+        top_10: scores.top_10,
+        top_10_std: scores.top_10_std,
+        top_10_percent: scores.top_10_percent,
+        top_10_percent_std: scores.top_10_percent_std,
+        top_1_wilson_lower: scores.top_1_ci.wilson.lower,
+        top_1_wilson_upper: scores.top_1_ci.wilson.upper,
+        top_1_bootstrap_lower: scores.top_1_ci.bootstrap.lower,
+        top_1_bootstrap_upper: scores.top_1_ci.bootstrap.upper,
+        top_10_wilson_lower: scores.top_10_ci.wilson.lower,
+        top_10_wilson_upper: scores.top_10_ci.wilson.upper,
+        top_10_bootstrap_lower: scores.top_10_ci.bootstrap.lower,
+        top_10_bootstrap_upper: scores.top_10_ci.bootstrap.upper,
+        top_10_percent_wilson_lower: scores.top_10_percent_ci.wilson.lower,
+        top_10_percent_wilson_upper: scores.top_10_percent_ci.wilson.upper,
+        top_10_percent_bootstrap_lower: scores.top_10_percent_ci.bootstrap.lower,
+        top_10_percent_bootstrap_upper: scores.top_10_percent_ci.bootstrap.upper,
+        mean_reciprocal_rank: scores.mean_reciprocal_rank,
+        median_rank: scores.median_rank,
+        top_k: scores
+            .top_k
+            .iter()
+            .map(|(k, rate)| format!("{}:{}", k, rate))
+            .collect::<Vec<String>>()
+            .join(";"),
+        top_k_wilson: format_intervals(&scores.top_k_ci, |intervals| &intervals.wilson),
+        top_k_bootstrap: format_intervals(&scores.top_k_ci, |intervals| &intervals.bootstrap),
+        cmc: scores
+            .cmc
+            .iter()
+            .map(|rate| rate.to_string())
+            .collect::<Vec<String>>()
+            .join(";"),
+        open_world_fraction: config.open_world_fraction,
+        rejection_threshold: config.rejection_threshold,
+        true_match_rate: open_world.and_then(|scores| scores.true_match_rate),
+        false_match_rate: open_world.and_then(|scores| scores.false_match_rate),
+        rejection_accuracy: open_world.and_then(|scores| scores.rejection_accuracy),
+        equal_error_rate: open_world.map(|scores| scores.equal_error_rate),
+        config: config_to_json(config)?.to_string(),
+    })
+}
+
+/// Appends the row to a CSV file. The header is only written to a new or empty file, the header of an existing file
+/// has to match the row.
+fn append_row(path: &str, row: &Row) -> Result<(), Box<dyn Error>> {
+    let mut buffer = WriterBuilder::new().has_headers(true).from_writer(Vec::new());
+    buffer.serialize(row)?;
+    let bytes = buffer.into_inner()?;
+    let header = ReaderBuilder::new().from_reader(bytes.as_slice()).headers()?.clone();
+
+    let is_empty = !Path::new(path).exists() || std::fs::metadata(path)?.len() == 0;
+    if !is_empty && ReaderBuilder::new().from_path(path)?.headers()? != &header {
+        return Err(format!(
+            "Error: The header of {:?} does not match the evaluation output of schema version {:?}. Choose another file with --output.",
+            path, OUTPUT_SCHEMA_VERSION
+        )
+        .into());
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut wtr = WriterBuilder::new().has_headers(is_empty).from_writer(file);
+    wtr.serialize(row)?;
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Writes the performance scores as well as the configuration to the output file. Files ending in `.json` hold an
+/// array of records that is extended by each run, files ending in `.jsonl` one record per line and all other files
+/// one CSV row per record.
+pub fn write_to_file(config: &Config, scores: &Scores) -> Result<(), Box<dyn Error>> {
+    let path = config.output.as_str();
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let record = Record {
+        schema_version: OUTPUT_SCHEMA_VERSION,
+        run_id: &config.run_id,
+        timestamp: &config.timestamp,
+        git_commit: GIT_COMMIT,
+        config: config_to_json(config)?,
+        scores: scores,
+    };
+    if path.ends_with(".jsonl") {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+    } else if path.ends_with(".json") {
+        let mut records: Vec<serde_json::Value> = match std::fs::read_to_string(path) {
+            Ok(content) if !content.trim().is_empty() => serde_json::from_str(&content)?,
+            _ => Vec::new(),
+        };
+        records.push(serde_json::to_value(&record)?);
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), &records)?;
+    } else {
+        append_row(path, &gen_row(config, scores)?)?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_10_percent_covers_small_samples() {
+        for num_users in 1..=10 {
+            assert_eq!(top_10_percent_cutoff(num_users), 1, "{}", num_users);
+        }
+        assert_eq!(top_10_percent_cutoff(0), 1);
+        assert_eq!(top_10_percent_cutoff(11), 2);
+        assert_eq!(top_10_percent_cutoff(20), 2);
+        assert_eq!(top_10_percent_cutoff(25), 3);
+        assert_eq!(top_10_percent_cutoff(1000), 100);
+    }
+}
@@ -0,0 +1,144 @@
+//! Runs the whole pipeline on a small synthetic population: generation, parsing, sampling and the linkage attacks of
+//! both approaches.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const BIN: &str = env!("CARGO_BIN_EXE_linkage-by-mobility-behavior");
+
+const NUM_USERS: usize = 30;
+
+/// Creates an empty working directory, since the binary writes its outputs relative to the working directory.
+fn work_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("linkage-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn run(dir: &Path, args: &[&str]) -> bool {
+    Command::new(BIN)
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .success()
+}
+
+#[test]
+fn generate_refuses_to_overwrite() {
+    let dir = work_dir("overwrite");
+    fs::write(dir.join("data.csv"), "original").unwrap();
+    assert!(!run(&dir, &["generate", "--output", "data.csv", "--num_users", "2"]));
+    assert_eq!(fs::read_to_string(dir.join("data.csv")).unwrap(), "original");
+    assert!(!run(&dir, &["generate", "--num_users", "2"]));
+    assert!(run(&dir, &["generate", "--output", "data.csv", "--num_users", "2", "--force"]));
+    assert_ne!(fs::read_to_string(dir.join("data.csv")).unwrap(), "original");
+}
+
+#[test]
+fn synthetic_users_are_linked() {
+    let dir = work_dir("pipeline");
+    let num_users = NUM_USERS.to_string();
+    assert!(run(
+        &dir,
+        &["generate", "--output", "synthetic.csv", "--num_users", &num_users, "--num_days", "14", "--seed", "1"]
+    ));
+
+    for approach in ["frequency", "sequence"].iter() {
+        assert!(run(
+            &dir,
+            &[
+                "--path",
+                "synthetic.csv",
+                "--approach",
+                approach,
+                "--user_sample_size",
+                &num_users,
+                "--max_trace_len",
+                "100",
+                "--output",
+                "evaluation.jsonl",
+            ]
+        ));
+    }
+
+    let records: Vec<serde_json::Value> = fs::read_to_string(dir.join("evaluation.jsonl"))
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    for record in records.iter() {
+        let scores = &record["scores"];
+        let approach = record["config"]["approach"].as_str().unwrap();
+        assert_eq!(scores["num_attacks"].as_u64(), Some(NUM_USERS as u64), "{}", approach);
+        // Without shared routes, most synthetic users are linked to themselves, far beyond chance level
+        let top_1 = scores["top_1"].as_f64().unwrap();
+        assert!(top_1 >= 0.5, "{}: top 1 of {:?}", approach, top_1);
+        assert!(scores["top_10"].as_f64().unwrap() >= top_1, "{}", approach);
+        assert!(scores["mean_reciprocal_rank"].as_f64().unwrap() >= top_1, "{}", approach);
+    }
+}
+
+/// Writes the rows of the dataset with the users in reverse order of appearance, keeping the rows of each user in
+/// order, and leaves out the rows of the given users.
+fn reorder_users(from: &Path, to: &Path, excluded: &[&str]) {
+    let content = fs::read_to_string(from).unwrap();
+    let mut lines = content.lines();
+    let header = lines.next().unwrap();
+    let mut blocks: Vec<(String, Vec<&str>)> = Vec::new();
+    for line in lines {
+        let user_id = line.split(',').next().unwrap();
+        match blocks.last_mut() {
+            Some((last, rows)) if last == user_id => rows.push(line),
+            _ => blocks.push((user_id.to_string(), vec![line])),
+        }
+    }
+    let rows: Vec<&str> = blocks
+        .iter()
+        .rev()
+        .filter(|(user_id, _)| !excluded.contains(&user_id.as_str()))
+        .flat_map(|(_, rows)| rows.iter().cloned())
+        .collect();
+    fs::write(to, format!("{}\n{}\n", header, rows.join("\n"))).unwrap();
+}
+
+fn read_json(path: &Path) -> serde_json::Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn sampling_maps_survive_reordered_input() {
+    let dir = work_dir("maps");
+    assert!(run(
+        &dir,
+        &["generate", "--output", "synthetic.csv", "--num_users", "20", "--num_days", "7", "--seed", "2"]
+    ));
+    reorder_users(&dir.join("synthetic.csv"), &dir.join("reordered.csv"), &[]);
+
+    let evaluate = |path: &str, maps: &[&str]| {
+        let mut args = vec!["--path", path, "--user_sample_size", "10", "--output", "evaluation.jsonl"];
+        args.extend_from_slice(maps);
+        run(&dir, &args)
+    };
+    assert!(evaluate("synthetic.csv", &["--save_map", "maps.json", "--user_id_map", "ids/users.csv"]));
+    assert!(dir.join("ids/users.csv").exists());
+    assert!(evaluate("reordered.csv", &["--path_to_map", "maps.json", "--save_map", "reloaded.json"]));
+
+    // The maps are keyed by the original user ids, so they select the same users and traces in both orders
+    let maps = read_json(&dir.join("maps.json"));
+    assert_eq!(maps, read_json(&dir.join("reloaded.json")));
+    let targets = maps["user_to_target_idx_map"].as_object().unwrap();
+    assert_eq!(targets.len(), 10);
+    assert!(targets.keys().all(|user_id| user_id.starts_with("user_")));
+
+    // Maps that refer to a user who is missing from the dataset are rejected
+    let target = targets.keys().next().unwrap().clone();
+    reorder_users(&dir.join("synthetic.csv"), &dir.join("missing.csv"), &[&target]);
+    assert!(!evaluate("missing.csv", &["--path_to_map", "maps.json"]));
+}